@@ -1,32 +1,54 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use arc_swap::access::DynAccess;
+use arc_swap::ArcSwap;
+use axum::body::Body;
 use axum::extract::{Path, Query, State};
-use axum::http::header::CONTENT_TYPE;
-use axum::http::Request;
+use axum::http::header::{
+    ACCEPT, AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, LAST_MODIFIED, LOCATION, VARY,
+};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::{self, Next};
 use axum::response::{Html, IntoResponse, Redirect, Response};
-use axum::routing::get;
+use axum::routing::{self, get};
 use axum::{Json, Router};
-use handlebars::Handlebars;
+use chrono::{DateTime, Datelike, SubsecRound, Utc};
+use handlebars::{html_escape, Handlebars};
 use include_dir::{include_dir, Dir};
 use indexmap::IndexMap;
-use rss::{Category, ChannelBuilder, ItemBuilder};
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::PrometheusHandle;
+use rss::{Category, ChannelBuilder, GuidBuilder, Image, ItemBuilder};
 use serde::{Deserialize, Serialize};
 use serde_value::Value;
 use tokio::sync::RwLock;
 use tower::service_fn;
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::services::ServeDir;
+use tower_http::set_header::SetResponseHeader;
 use tower_http::trace::TraceLayer;
-use tracing::{info, info_span, Span};
+use tracing::{error, info, info_span, warn, Span};
 
-use crate::config::{DirsConfig, RssConfig, StyleConfig};
-use crate::error::{AppError, AppResult};
-use crate::path::SafePath;
-use crate::post::{Filter, PostManager, PostMetadata, RenderStats, ReturnedPost};
+use crate::color::Theme;
+use crate::config::{
+    AdminConfig, CacheControlConfig, Config, JsonConfig, Precompressed, RobotsConfig, RssConfig,
+    Sort, StyleConfig,
+};
+use crate::error::{AppError, AppResult, ErrorMessage, PostError};
+use crate::path::{with_base_path, SafePath};
+use crate::post::{
+    cache::CacheStats, listing_filters, sort_series, ListingQuery, Order, PostManager,
+    PostMetadata, RenderStats, ReturnedPost, TagMatch,
+};
+use crate::rate_limit::{self, RateLimiter};
 use crate::serve_dir_included::handle;
+use crate::text::strip_html;
 
-const STATIC: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/static");
+pub(crate) const STATIC: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/static");
 
 #[derive(Serialize)]
 pub struct BingusInfo {
@@ -41,13 +63,37 @@ const BINGUS_INFO: BingusInfo = BingusInfo {
     repository: env!("CARGO_PKG_REPOSITORY"),
 };
 
+/// site-wide info, given to every template as `site`
+#[derive(Serialize)]
+struct SiteContext {
+    post_count: usize,
+    version: &'static str,
+    year: i32,
+    base_url: String,
+}
+
+#[derive(Serialize)]
+struct WithSite<'a, T> {
+    #[serde(flatten)]
+    page: &'a T,
+    site: &'a SiteContext,
+}
+
 #[derive(Clone)]
 #[non_exhaustive]
 pub struct AppState {
     pub rss: Arc<dyn DynAccess<RssConfig> + Send + Sync>,
+    pub json: Arc<dyn DynAccess<JsonConfig> + Send + Sync>,
+    pub cache_control: Arc<dyn DynAccess<CacheControlConfig> + Send + Sync>,
     pub style: Arc<dyn DynAccess<StyleConfig> + Send + Sync>,
+    pub max_posts: Arc<dyn DynAccess<usize> + Send + Sync>,
+    pub base_path: Arc<dyn DynAccess<Box<str>> + Send + Sync>,
+    pub admin: Arc<dyn DynAccess<AdminConfig> + Send + Sync>,
+    pub robots: Arc<dyn DynAccess<RobotsConfig> + Send + Sync>,
     pub posts: Arc<dyn PostManager + Send + Sync>,
+    pub listed_posts: Arc<ListedPosts>,
     pub templates: Arc<RwLock<Handlebars<'static>>>,
+    pub metrics: Option<PrometheusHandle>,
 }
 
 #[derive(Serialize)]
@@ -59,12 +105,41 @@ struct IndexTemplate<'a> {
     tags: IndexMap<Arc<str>, u64>,
     joined_tags: String,
     style: &'a StyleConfig,
+    theme: Option<Theme>,
+    /// set on author pages
+    author: Option<&'a str>,
 }
 
 #[derive(Serialize)]
 struct PostsTemplate<'a> {
     bingus_info: &'a BingusInfo,
     posts: Vec<PostMetadata>,
+    rss: bool,
+    js: bool,
+    tags: IndexMap<Arc<str>, u64>,
+    style: &'a StyleConfig,
+}
+
+#[derive(Serialize)]
+struct TagsTemplate<'a> {
+    bingus_info: &'a BingusInfo,
+    tags: IndexMap<Arc<str>, u64>,
+    js: bool,
+    style: &'a StyleConfig,
+}
+
+#[derive(Serialize)]
+struct NotFoundTemplate<'a> {
+    bingus_info: &'a BingusInfo,
+    message: &'a str,
+    style: &'a StyleConfig,
+}
+
+#[derive(Serialize)]
+struct SeriesTemplate<'a> {
+    bingus_info: &'a BingusInfo,
+    name: &'a str,
+    posts: Vec<PostMetadata>,
     js: bool,
     style: &'a StyleConfig,
 }
@@ -76,14 +151,115 @@ struct PostTemplate<'a> {
     body: Arc<str>,
     perf: RenderStats,
     js: bool,
+    /// the body has diagrams for mermaid.js
+    mermaid: bool,
     color: Option<&'a str>,
+    theme: Option<Theme>,
+    canonical_url: String,
+    image_url: Option<String>,
+    json_ld: String,
     joined_tags: String,
     style: &'a StyleConfig,
     raw_name: Option<String>,
+    related: Vec<PostMetadata>,
+    series_prev: Option<PostMetadata>,
+    series_next: Option<PostMetadata>,
+}
+
+#[derive(Serialize)]
+struct JsonLdPerson<'a> {
+    #[serde(rename = "@type")]
+    type_: &'static str,
+    name: &'a str,
+}
+
+/// schema.org BlogPosting, embedded in posts for search engines
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonLd<'a> {
+    #[serde(rename = "@context")]
+    context: &'static str,
+    #[serde(rename = "@type")]
+    type_: &'static str,
+    headline: &'a str,
+    description: &'a str,
+    author: JsonLdPerson<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_published: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_modified: Option<DateTime<Utc>>,
+    keywords: &'a str,
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<&'a str>,
+}
+
+impl JsonLd<'_> {
+    /// serializes to JSON that is safe to embed in a `<script>` tag
+    fn to_script(&self) -> serde_json::Result<String> {
+        Ok(serde_json::to_string(self)?
+            .replace('<', "\\u003c")
+            .replace('>', "\\u003e")
+            .replace('&', "\\u0026"))
+    }
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    posts_root_ok: bool,
 }
 
 #[derive(Deserialize)]
 struct QueryParams {
+    tag: Option<String>,
+    #[serde(rename = "n")]
+    num_posts: Option<usize>,
+    /// comma-separated
+    tags: Option<String>,
+    #[serde(rename = "match", default)]
+    tag_match: TagMatch,
+    /// comma-separated
+    exclude: Option<String>,
+    author: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    sort: Option<Sort>,
+    order: Option<Order>,
+    #[serde(flatten)]
+    other: IndexMap<String, Value>,
+}
+
+fn split_list(list: &Option<String>) -> impl Iterator<Item = &str> {
+    list.iter()
+        .flat_map(|list| list.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+impl QueryParams {
+    fn listing(&self) -> ListingQuery<'_> {
+        ListingQuery {
+            // `tag` and `tags` together
+            tags: self
+                .tag
+                .as_deref()
+                .into_iter()
+                .chain(split_list(&self.tags))
+                .collect(),
+            tag_match: self.tag_match,
+            exclude: split_list(&self.exclude).collect(),
+            author: self.author.as_deref(),
+            from: self.from,
+            to: self.to,
+            series: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    q: String,
     tag: Option<String>,
     #[serde(rename = "n")]
     num_posts: Option<usize>,
@@ -91,6 +267,75 @@ struct QueryParams {
     other: IndexMap<String, Value>,
 }
 
+/// clamps the `n` query parameter to the configured maximum
+fn limit(
+    num_posts: Option<usize>,
+    max_posts: &Arc<dyn DynAccess<usize> + Send + Sync>,
+) -> Option<usize> {
+    num_posts.map(|n| n.min(*max_posts.load()))
+}
+
+/// how long the listed posts are reused, so pages showing things about them
+/// don't go through every post on each request
+const LISTED_POSTS_TTL: Duration = Duration::from_secs(60);
+
+/// the metadata of every published post, for the post count in
+/// [`SiteContext`] and the related and series links under posts
+#[derive(Default)]
+pub struct ListedPosts {
+    /// when the posts were last listed, also held while listing so
+    /// concurrent requests don't all list them
+    listed_at: tokio::sync::Mutex<Option<Instant>>,
+    posts: ArcSwap<Vec<PostMetadata>>,
+}
+
+impl ListedPosts {
+    /// the listed posts, listed again from (cached) metadata if they're stale
+    async fn get(&self, posts: &(dyn PostManager + Send + Sync)) -> Arc<Vec<PostMetadata>> {
+        let mut listed_at = self.listed_at.lock().await;
+        if listed_at.is_some_and(|at| at.elapsed() < LISTED_POSTS_TTL) {
+            return self.last();
+        }
+
+        let listing = ListingQuery::default();
+        match posts
+            .get_all_post_metadata(&listing_filters(&listing), &IndexMap::new())
+            .await
+        {
+            Ok(posts) => self.posts.store(Arc::new(posts)),
+            Err(err) => error!("failed to list posts: {err}"),
+        }
+        *listed_at = Some(Instant::now());
+
+        self.last()
+    }
+
+    /// the last listed posts, without listing them again
+    fn last(&self) -> Arc<Vec<PostMetadata>> {
+        self.posts.load_full()
+    }
+}
+
+fn site_context(
+    post_count: usize,
+    rss: &Arc<dyn DynAccess<RssConfig> + Send + Sync>,
+    base_path: &Arc<dyn DynAccess<Box<str>> + Send + Sync>,
+) -> SiteContext {
+    let base_url = {
+        let link = &rss.load().link;
+        link.join(&with_base_path(&base_path.load(), "/"))
+            .unwrap_or_else(|_| link.clone())
+            .to_string()
+    };
+
+    SiteContext {
+        post_count,
+        version: BINGUS_INFO.version,
+        year: Utc::now().year(),
+        base_url,
+    }
+}
+
 fn collect_tags(posts: &Vec<PostMetadata>) -> IndexMap<Arc<str>, u64> {
     let mut tags = IndexMap::new();
 
@@ -110,6 +355,96 @@ fn collect_tags(posts: &Vec<PostMetadata>) -> IndexMap<Arc<str>, u64> {
     tags
 }
 
+/// the posts before and after the given one in its series
+fn series_neighbours(
+    meta: &PostMetadata,
+    posts: &[PostMetadata],
+) -> (Option<PostMetadata>, Option<PostMetadata>) {
+    let Some(series) = &meta.series else {
+        return (None, None);
+    };
+    let mut series: Vec<PostMetadata> = posts
+        .iter()
+        .filter(|post| post.series.as_ref() == Some(series))
+        .cloned()
+        .collect();
+    sort_series(&mut series);
+
+    match series.iter().position(|post| post.name == meta.name) {
+        Some(i) => (
+            i.checked_sub(1).map(|i| series[i].clone()),
+            series.get(i + 1).cloned(),
+        ),
+        None => (None, None),
+    }
+}
+
+/// other posts linked to under a post, which aren't part of its body but
+/// have to be covered by its validators all the same
+#[derive(Default)]
+struct LinkedPosts {
+    related: Vec<PostMetadata>,
+    series_prev: Option<PostMetadata>,
+    series_next: Option<PostMetadata>,
+}
+
+impl LinkedPosts {
+    fn new(meta: &PostMetadata, posts: &[PostMetadata], related_count: usize) -> Self {
+        let related = if related_count > 0 && !meta.tags.is_empty() {
+            related_posts(meta, posts, related_count)
+        } else {
+            Vec::new()
+        };
+        let (series_prev, series_next) = series_neighbours(meta, posts);
+        Self {
+            related,
+            series_prev,
+            series_next,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.related.is_empty() && self.series_prev.is_none() && self.series_next.is_none()
+    }
+
+    fn hash(&self, hasher: &mut impl Hasher) {
+        self.related.len().hash(hasher);
+        for related in &self.related {
+            hash_listed(related, hasher);
+        }
+        for neighbour in [&self.series_prev, &self.series_next] {
+            neighbour.is_some().hash(hasher);
+            if let Some(neighbour) = neighbour {
+                hash_listed(neighbour, hasher);
+            }
+        }
+    }
+}
+
+fn related_posts(meta: &PostMetadata, posts: &[PostMetadata], n: usize) -> Vec<PostMetadata> {
+    let mut related: Vec<(usize, &PostMetadata)> = posts
+        .iter()
+        .filter(|post| post.name != meta.name)
+        .filter_map(|post| {
+            let shared = post
+                .tags
+                .iter()
+                .filter(|tag| meta.tags.contains(tag))
+                .count();
+            (shared > 0).then_some((shared, post))
+        })
+        .collect();
+
+    related.sort_by(|(shared1, post1), (shared2, post2)| {
+        shared2
+            .cmp(shared1)
+            .then_with(|| post2.written_at.cmp(&post1.written_at))
+    });
+    related.truncate(n);
+
+    related.into_iter().map(|(_, post)| post.clone()).collect()
+}
+
 fn join_tags_for_meta(tags: &IndexMap<Arc<str>, u64>, delim: &str) -> String {
     let mut s = String::new();
     let tags = tags.keys().enumerate();
@@ -123,87 +458,615 @@ fn join_tags_for_meta(tags: &IndexMap<Arc<str>, u64>, delim: &str) -> String {
     s
 }
 
-async fn index(
+fn count_matches(meta: &PostMetadata, body: &str, terms: &[String]) -> usize {
+    let mut haystack = String::new();
+    haystack += &meta.title;
+    haystack.push('\n');
+    haystack += &meta.description;
+    haystack.push('\n');
+    for tag in &meta.tags {
+        haystack += tag;
+        haystack.push('\n');
+    }
+    haystack += &strip_html(body);
+    let haystack = haystack.to_lowercase();
+
+    terms
+        .iter()
+        .map(|term| haystack.matches(term.as_str()).count())
+        .sum()
+}
+
+fn cache_control_header(value: Option<&str>) -> Option<[(HeaderName, HeaderValue); 1]> {
+    let value = value?;
+    match HeaderValue::from_str(value) {
+        Ok(value) => Some([(CACHE_CONTROL, value)]),
+        Err(err) => {
+            error!("invalid cache-control value {value:?}: {err}");
+            None
+        }
+    }
+}
+
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// whether the client's copy, going by `If-Modified-Since`, is still fresh
+fn not_modified_since(headers: &HeaderMap, modified_at: DateTime<Utc>) -> bool {
+    headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        // http dates only have second precision
+        .is_some_and(|since| since >= modified_at.trunc_subsecs(0))
+}
+
+fn etag(body: &str, render_hash: u64, linked: &LinkedPosts) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    render_hash.hash(&mut hasher);
+    linked.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// hashes what listings and the feed show of a post, its body included
+fn hash_listed(metadata: &PostMetadata, hasher: &mut impl Hasher) {
+    metadata.name.hash(hasher);
+    metadata.url_name().hash(hasher);
+    metadata.title.hash(hasher);
+    metadata.description.hash(hasher);
+    metadata.excerpt_html.hash(hasher);
+    metadata.author.hash(hasher);
+    metadata.tags.hash(hasher);
+    metadata.written_at.hash(hasher);
+    metadata.modified_at.hash(hasher);
+    metadata.content_hash.hash(hasher);
+}
+
+/// newest first, like the index, so `rss.max_items` keeps the newest posts
+fn sort_newest_first<T>(posts: &mut [T], metadata: impl Fn(&T) -> &PostMetadata) {
+    posts.sort_by(|a, b| {
+        let (a, b) = (metadata(a), metadata(b));
+        (b.written_at, b.modified_at, &b.name).cmp(&(a.written_at, a.modified_at, &a.name))
+    });
+}
+
+/// changes whenever a post in the feed, or how the feed is put together,
+/// changes, without having to render any of them
+fn feed_etag(
+    mut posts: Vec<PostMetadata>,
+    rss: &RssConfig,
+    style: &StyleConfig,
+    base_path: &str,
+) -> String {
+    sort_newest_first(&mut posts, |metadata| metadata);
+    if let Some(max_items) = rss.max_items {
+        posts.truncate(max_items);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    for metadata in &posts {
+        hash_listed(metadata, &mut hasher);
+    }
+    rss.link.hash(&mut hasher);
+    rss.language.hash(&mut hasher);
+    rss.image.hash(&mut hasher);
+    rss.full_content.hash(&mut hasher);
+    rss.max_items.hash(&mut hasher);
+    style.title.hash(&mut hasher);
+    style.description.hash(&mut hasher);
+    base_path.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+fn etag_matches(if_none_match: &HeaderValue, etag: &str) -> bool {
+    let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        // If-None-Match uses weak comparison
+        candidate == "*" || candidate.trim_start_matches("W/") == etag
+    })
+}
+
+fn record_render_stats(perf: &RenderStats) {
+    let (source, duration) = match perf {
+        RenderStats::Cached(duration) => ("cached", Some(duration)),
+        RenderStats::Rendered { total, .. } => ("rendered", Some(total)),
+        RenderStats::Fetched(duration) => ("fetched", Some(duration)),
+        RenderStats::Other { time, .. } => ("other", Some(time)),
+        RenderStats::Unknown => ("unknown", None),
+    };
+
+    counter!("bingus_posts_rendered_total", "source" => source).increment(1);
+    if let Some(duration) = duration {
+        histogram!("bingus_render_duration_seconds", "source" => source)
+            .record(duration.as_secs_f64());
+    }
+}
+
+async fn metrics(State(AppState { metrics, .. }): State<AppState>) -> AppResult<Response> {
+    let Some(handle) = metrics else {
+        return Err(AppError::MetricsDisabled);
+    };
+
+    Ok((
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        handle.render(),
+    )
+        .into_response())
+}
+
+/// compares in constant time so the token can't be guessed byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn authorize(config: &AdminConfig, headers: &HeaderMap) -> AppResult<()> {
+    let Some(token) = &config.token else {
+        return Err(AppError::AdminDisabled);
+    };
+
+    let given = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match given {
+        Some(given) if constant_time_eq(given.as_bytes(), token.as_bytes()) => Ok(()),
+        _ => Err(AppError::Unauthorized),
+    }
+}
+
+#[derive(Serialize)]
+struct CachePurged {
+    removed: usize,
+}
+
+async fn purge_cache(
+    State(AppState { admin, posts, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> AppResult<Json<CachePurged>> {
+    authorize(&admin.load(), &headers)?;
+
+    let removed = posts.clear_cache().await;
+    info!("purged {removed} entries from cache");
+
+    Ok(Json(CachePurged { removed }))
+}
+
+async fn cache_stats(
+    State(AppState { admin, posts, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> AppResult<Json<Option<CacheStats>>> {
+    authorize(&admin.load(), &headers)?;
+
+    Ok(Json(posts.cache_stats()))
+}
+
+async fn healthz(State(AppState { posts, .. }): State<AppState>) -> impl IntoResponse {
+    let posts_root_ok = posts.root_ok().await;
+    let (code, status) = if posts_root_ok {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    (
+        code,
+        Json(Health {
+            status,
+            posts_root_ok,
+        }),
+    )
+}
+
+async fn robots(
+    State(AppState {
+        robots,
+        rss,
+        base_path,
+        ..
+    }): State<AppState>,
+) -> AppResult<impl IntoResponse> {
+    let robots = robots.load();
+    let rss = rss.load();
+    let base_path = base_path.load();
+
+    let mut body = String::from("User-agent: *\n");
+    if robots.disallow.is_empty() {
+        // an empty Disallow allows everything
+        body.push_str("Disallow:\n");
+    }
+    for path in &robots.disallow {
+        body.push_str(&format!("Disallow: {}\n", with_base_path(&base_path, path)));
+    }
+    let sitemap = match &robots.sitemap {
+        Some(sitemap) => Some(&**sitemap),
+        // crawlers accept an rss feed as a sitemap
+        None if rss.enable => Some("/feed.xml"),
+        None => None,
+    };
+    if let Some(sitemap) = sitemap {
+        let url = rss.link.join(&with_base_path(&base_path, sitemap))?;
+        body.push_str(&format!("\nSitemap: {url}\n"));
+    }
+
+    Ok(([(CONTENT_TYPE, "text/plain; charset=utf-8")], body))
+}
+
+async fn opensearch(
     State(AppState {
         rss,
         style,
-        posts,
-        templates,
+        base_path,
         ..
     }): State<AppState>,
-    Query(query): Query<QueryParams>,
 ) -> AppResult<impl IntoResponse> {
+    let style = style.load();
+    let (search, this) = {
+        let link = &rss.load().link;
+        let base_path = base_path.load();
+        (
+            link.join(&format!("{}/search", *base_path))?,
+            link.join(&format!("{}/opensearch.xml", *base_path))?,
+        )
+    };
+    // the spec limits short names to 16 characters
+    let short_name: String = style.title.chars().take(16).collect();
+
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <ShortName>{}</ShortName>
+  <Description>{}</Description>
+  <InputEncoding>UTF-8</InputEncoding>
+  <Url type="text/html" method="get" template="{}?q={{searchTerms}}"/>
+  <Url type="application/opensearchdescription+xml" rel="self" template="{}"/>
+</OpenSearchDescription>
+"#,
+        html_escape(&short_name),
+        html_escape(&style.description),
+        html_escape(search.as_str()),
+        html_escape(this.as_str()),
+    );
+
+    Ok((
+        [(CONTENT_TYPE, "application/opensearchdescription+xml")],
+        body,
+    ))
+}
+
+/// whether the client prefers JSON over HTML, going by the `Accept` header
+fn prefers_json(headers: &HeaderMap) -> bool {
+    let mut html = 0.0;
+    let mut json = 0.0;
+
+    for accept in headers.get_all(ACCEPT) {
+        let Ok(accept) = accept.to_str() else {
+            continue;
+        };
+        for range in accept.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default();
+            let q = params
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            match media_type {
+                "text/html" => html = q.max(html),
+                "application/json" => json = q.max(json),
+                _ => {}
+            }
+        }
+    }
+
+    json > html
+}
+
+fn posts_json_response(json: &JsonConfig, posts: Vec<PostMetadata>) -> AppResult<Response> {
+    if json.content_hash {
+        return Ok(Json(posts).into_response());
+    }
+
     let posts = posts
+        .into_iter()
+        .map(|meta| {
+            let mut value = serde_json::to_value(meta)?;
+            if let Some(object) = value.as_object_mut() {
+                object.remove("body_bytes");
+                object.remove("content_hash");
+            }
+            Ok(value)
+        })
+        .collect::<Result<Vec<_>, serde_json::Error>>()
+        .map_err(PostError::from)?;
+
+    Ok(Json(posts).into_response())
+}
+
+async fn index(
+    State(state): State<AppState>,
+    Query(query): Query<QueryParams>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    render_index(state, query, headers).await
+}
+
+async fn author(
+    State(state): State<AppState>,
+    Path(author): Path<String>,
+    Query(mut query): Query<QueryParams>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    query.author = Some(author);
+    render_index(state, query, headers).await
+}
+
+async fn tag(
+    State(state): State<AppState>,
+    Path(tag): Path<String>,
+    Query(mut query): Query<QueryParams>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    query.tag = Some(tag);
+    render_index(state, query, headers).await
+}
+
+async fn render_index(
+    AppState {
+        rss,
+        json,
+        style,
+        max_posts,
+        base_path,
+        posts: manager,
+        listed_posts,
+        templates,
+        cache_control,
+        ..
+    }: AppState,
+    query: QueryParams,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let sort = query.sort.unwrap_or(style.load().default_sort);
+    let listing = query.listing();
+    let posts = manager
         .get_max_n_post_metadata_with_optional_tag_sorted(
-            query.num_posts,
-            query.tag.as_deref(),
+            limit(query.num_posts, &max_posts),
+            &listing_filters(&listing),
+            sort,
+            query.order,
             &query.other,
         )
         .await?;
 
+    let cache_control = cache_control_header(cache_control.load().index.as_deref());
+    let vary = [(VARY, "Accept")];
+
+    if prefers_json(&headers) {
+        let response = posts_json_response(&json.load(), posts)?;
+        return Ok((cache_control, vary, response).into_response());
+    }
+
     let tags = collect_tags(&posts);
     let joined_tags = join_tags_for_meta(&tags, ", ");
+    let site = site_context(listed_posts.get(&*manager).await.len(), &rss, &base_path);
 
     let reg = templates.read().await;
     let style = style.load();
     let rendered = reg.render(
         "index",
-        &IndexTemplate {
-            bingus_info: &BINGUS_INFO,
-            posts,
-            rss: rss.load().enable,
-            js: style.js_enable,
-            tags,
-            joined_tags,
-            style: &style,
+        &WithSite {
+            site: &site,
+            page: &IndexTemplate {
+                bingus_info: &BINGUS_INFO,
+                posts,
+                rss: rss.load().enable,
+                js: style.js_enable,
+                tags,
+                joined_tags,
+                theme: style.default_color.as_deref().and_then(Theme::from_color),
+                author: query.author.as_deref(),
+                style: &style,
+            },
         },
     );
     drop((style, reg));
 
-    Ok(Html(rendered?))
+    Ok((cache_control, vary, Html(rendered?)).into_response())
 }
 
 async fn posts_json(
-    State(AppState { posts, .. }): State<AppState>,
+    State(AppState {
+        json,
+        style,
+        max_posts,
+        posts,
+        ..
+    }): State<AppState>,
     Query(query): Query<QueryParams>,
-) -> AppResult<Json<Vec<PostMetadata>>> {
+) -> AppResult<Response> {
+    let sort = query.sort.unwrap_or(style.load().default_sort);
+    let listing = query.listing();
     let posts = posts
         .get_max_n_post_metadata_with_optional_tag_sorted(
-            query.num_posts,
-            query.tag.as_deref(),
+            limit(query.num_posts, &max_posts),
+            &listing_filters(&listing),
+            sort,
+            query.order,
             &query.other,
         )
         .await?;
 
-    Ok(Json(posts))
+    posts_json_response(&json.load(), posts)
+}
+
+async fn search(
+    State(AppState {
+        max_posts, posts, ..
+    }): State<AppState>,
+    Query(query): Query<SearchParams>,
+) -> AppResult<Json<Vec<PostMetadata>>> {
+    let terms: Vec<String> = query.q.split_whitespace().map(str::to_lowercase).collect();
+    if terms.is_empty() {
+        return Ok(Json(Vec::new()));
+    }
+
+    let listing = ListingQuery {
+        tags: query.tag.as_deref().into_iter().collect(),
+        ..Default::default()
+    };
+    let posts = posts
+        .get_all_posts(&listing_filters(&listing), &query.other)
+        .await?;
+
+    let mut results: Vec<(usize, PostMetadata)> = posts
+        .into_iter()
+        .filter_map(|(meta, body, _)| {
+            let count = count_matches(&meta, &body, &terms);
+            (count > 0).then_some((count, meta))
+        })
+        .collect();
+
+    // newest first among posts with the same number of matches
+    results.sort_by_key(|(_, meta)| meta.written_at.unwrap_or_default());
+    results.reverse();
+    results.sort_by(|(a, _), (b, _)| b.cmp(a));
+    if let Some(n) = limit(query.num_posts, &max_posts) {
+        results.truncate(n);
+    }
+
+    Ok(Json(results.into_iter().map(|(_, meta)| meta).collect()))
 }
 
 async fn posts(
     State(AppState {
-        posts,
+        rss,
+        max_posts,
+        base_path,
+        posts: manager,
+        listed_posts,
         templates,
         style,
         ..
     }): State<AppState>,
     Query(query): Query<QueryParams>,
 ) -> AppResult<Html<String>> {
-    let posts = posts
+    let sort = query.sort.unwrap_or(style.load().default_sort);
+    let listing = query.listing();
+    let posts = manager
         .get_max_n_post_metadata_with_optional_tag_sorted(
-            query.num_posts,
-            query.tag.as_deref(),
+            limit(query.num_posts, &max_posts),
+            &listing_filters(&listing),
+            sort,
+            query.order,
             &query.other,
         )
         .await?;
+    let tags = collect_tags(&posts);
+    let site = site_context(listed_posts.get(&*manager).await.len(), &rss, &base_path);
 
     let reg = templates.read().await;
     let style = style.load();
     let rendered = reg.render(
         "posts",
-        &PostsTemplate {
-            bingus_info: &BINGUS_INFO,
-            posts,
-            js: style.js_enable,
-            style: &style,
+        &WithSite {
+            site: &site,
+            page: &PostsTemplate {
+                bingus_info: &BINGUS_INFO,
+                posts,
+                rss: rss.load().enable,
+                js: style.js_enable,
+                tags,
+                style: &style,
+            },
+        },
+    );
+    drop((style, reg));
+
+    Ok(Html(rendered?))
+}
+
+async fn tags(
+    State(AppState {
+        rss,
+        base_path,
+        posts: manager,
+        listed_posts,
+        templates,
+        style,
+        ..
+    }): State<AppState>,
+    Query(query): Query<QueryParams>,
+) -> AppResult<Html<String>> {
+    let listing = ListingQuery::default();
+    let posts = manager
+        .get_all_post_metadata(&listing_filters(&listing), &query.other)
+        .await?;
+    let tags = collect_tags(&posts);
+    let site = site_context(listed_posts.get(&*manager).await.len(), &rss, &base_path);
+
+    let reg = templates.read().await;
+    let style = style.load();
+    let rendered = reg.render(
+        "tags",
+        &WithSite {
+            site: &site,
+            page: &TagsTemplate {
+                bingus_info: &BINGUS_INFO,
+                tags,
+                js: style.js_enable,
+                style: &style,
+            },
+        },
+    );
+    drop((style, reg));
+
+    Ok(Html(rendered?))
+}
+
+async fn series(
+    State(AppState {
+        rss,
+        base_path,
+        posts: manager,
+        listed_posts,
+        templates,
+        style,
+        ..
+    }): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<QueryParams>,
+) -> AppResult<Html<String>> {
+    let listing = ListingQuery {
+        series: Some(&name),
+        ..Default::default()
+    };
+    let mut posts = manager
+        .get_all_post_metadata(&listing_filters(&listing), &query.other)
+        .await?;
+    if posts.is_empty() {
+        return Err(PostError::NotFound(name.into()).into());
+    }
+    sort_series(&mut posts);
+    let site = site_context(listed_posts.get(&*manager).await.len(), &rss, &base_path);
+
+    let reg = templates.read().await;
+    let style = style.load();
+    let rendered = reg.render(
+        "series",
+        &WithSite {
+            site: &site,
+            page: &SeriesTemplate {
+                bingus_info: &BINGUS_INFO,
+                name: &name,
+                posts,
+                js: style.js_enable,
+                style: &style,
+            },
         },
     );
     drop((style, reg));
@@ -213,132 +1076,480 @@ async fn posts(
 
 async fn rss(
     State(AppState {
-        rss, style, posts, ..
+        rss,
+        style,
+        base_path,
+        posts,
+        cache_control,
+        ..
     }): State<AppState>,
     Query(query): Query<QueryParams>,
+    headers: HeaderMap,
 ) -> AppResult<Response> {
     if !rss.load().enable {
         return Err(AppError::RssDisabled);
     }
 
-    let posts = posts
-        .get_all_posts(
-            query
-                .tag
-                .as_ref()
-                .and(Some(Filter::Tags(query.tag.as_deref().as_slice())))
-                .as_slice(),
-            &query.other,
-        )
-        .await?;
+    let cache_control = cache_control_header(cache_control.load().feed.as_deref());
+    let listing = query.listing();
+    let filters = listing_filters(&listing);
+
+    // metadata is cheap to get, so pollers can be turned away before rendering
+    let etag = feed_etag(
+        posts.get_all_post_metadata(&filters, &query.other).await?,
+        &rss.load(),
+        &style.load(),
+        &base_path.load(),
+    );
+    if headers
+        .get(IF_NONE_MATCH)
+        .is_some_and(|if_none_match| etag_matches(if_none_match, &etag))
+    {
+        return Ok((StatusCode::NOT_MODIFIED, cache_control, [(ETAG, etag)]).into_response());
+    }
+
+    let mut posts = posts.get_all_posts(&filters, &query.other).await?;
 
     let rss = rss.load();
+    sort_newest_first(&mut posts, |(metadata, ..)| metadata);
+    if let Some(max_items) = rss.max_items {
+        posts.truncate(max_items);
+    }
     let style = style.load();
+    let base_path = base_path.load();
     let mut channel = ChannelBuilder::default();
     channel
         .title(&*style.title)
         .link(rss.link.to_string())
-        .description(&*style.description);
-    //TODO: .language()
+        .description(&*style.description)
+        .language(rss.language.as_deref().map(str::to_string))
+        .image(rss.image.as_ref().map(|image| Image {
+            url: image.to_string(),
+            title: style.title.to_string(),
+            link: rss.link.to_string(),
+            ..Default::default()
+        }));
 
     for (metadata, content, _) in posts {
+        // summary-only feeds leave out the rendered post
+        let content = rss.full_content.then(|| content.to_string());
+        let link = rss
+            .link
+            .join(&format!("{}/posts/{}", *base_path, metadata.url_name()))?
+            .to_string();
         channel.item(
             ItemBuilder::default()
                 .title(metadata.title.to_string())
-                .description(metadata.description.to_string())
+                .description(
+                    metadata
+                        .excerpt_html
+                        .as_deref()
+                        .unwrap_or(&metadata.description)
+                        .to_string(),
+                )
                 .author(metadata.author.to_string())
                 .categories(
                     metadata
                         .tags
-                        .into_iter()
+                        .iter()
                         .map(|tag| Category {
                             name: tag.to_string(),
                             domain: None,
                         })
                         .collect::<Vec<Category>>(),
                 )
-                .pub_date(metadata.written_at.map(|date| date.to_rfc2822()))
-                .content(content.to_string())
-                .link(
-                    rss.link
-                        .join(&format!("/posts/{}", metadata.name))?
-                        .to_string(),
+                // edits don't change the date, unless it's all there is
+                .pub_date(
+                    metadata
+                        .written_at
+                        .or(metadata.modified_at)
+                        .map(|date| date.to_rfc2822()),
                 )
+                .content(content)
+                // the same across edits, so readers don't see them as new posts
+                .guid(
+                    GuidBuilder::default()
+                        .value(link.clone())
+                        .permalink(true)
+                        .build(),
+                )
+                .link(link)
                 .build(),
         );
     }
-    drop((style, rss));
+    drop((style, rss, base_path));
 
     let body = channel.build().to_string();
     drop(channel);
 
-    Ok(([(CONTENT_TYPE, "text/xml")], body).into_response())
+    Ok((
+        cache_control,
+        [(ETAG, etag)],
+        [(CONTENT_TYPE, "text/xml")],
+        body,
+    )
+        .into_response())
+}
+
+async fn post_redirect(
+    State(AppState { base_path, .. }): State<AppState>,
+    Path(name): Path<String>,
+) -> Redirect {
+    Redirect::to(&with_base_path(
+        &base_path.load(),
+        &format!("/posts/{name}"),
+    ))
 }
 
 async fn post(
     State(AppState {
+        rss,
         style,
+        base_path,
         posts,
+        listed_posts,
         templates,
+        cache_control,
         ..
     }): State<AppState>,
     SafePath(name): SafePath<Arc<str>>,
     Query(query): Query<QueryParams>,
+    headers: HeaderMap,
 ) -> AppResult<impl IntoResponse> {
-    match posts.get_post(name.clone(), &query.other).await? {
+    let cache_control = cache_control_header(cache_control.load().post.as_deref());
+
+    let post = match posts.get_post(name.clone(), &query.other, &headers).await {
+        Err(PostError::NotFound(_)) if let Some(target) = posts.resolve_alias(&name).await => {
+            let location =
+                with_base_path(&base_path.load(), &format!("/posts/{target}")).into_owned();
+            return Ok((StatusCode::MOVED_PERMANENTLY, [(LOCATION, location)]).into_response());
+        }
+        result => result?,
+    };
+    counter!("bingus_posts_served_total").increment(1);
+    if let ReturnedPost::Rendered { perf, .. } = &post {
+        record_render_stats(perf);
+    }
+    let (listed, linked) = match &post {
+        ReturnedPost::Rendered { meta, .. } => {
+            let listed = listed_posts.get(&*posts).await;
+            let linked = LinkedPosts::new(meta, &listed, style.load().related_count);
+            (listed, linked)
+        }
+        ReturnedPost::Raw { .. } => Default::default(),
+    };
+    let modified_at = match &post {
+        ReturnedPost::Rendered { meta, .. } => meta.modified_at,
+        ReturnedPost::Raw { modified_at, .. } => *modified_at,
+    };
+    // the post's date doesn't change with the posts it links to, so only the
+    // ETag can tell whether those did
+    let modified_at = modified_at.filter(|_| linked.is_empty());
+    let last_modified = modified_at.map(|date| [(LAST_MODIFIED, http_date(date))]);
+    let etag = match &post {
+        ReturnedPost::Rendered {
+            body, render_hash, ..
+        } => Some(etag(body, *render_hash, &linked)),
+        ReturnedPost::Raw { .. } => None,
+    };
+
+    // If-None-Match takes precedence over If-Modified-Since when both are sent
+    let fresh = match (&etag, headers.get(IF_NONE_MATCH)) {
+        (Some(etag), Some(if_none_match)) => etag_matches(if_none_match, etag),
+        _ => modified_at.is_some_and(|date| not_modified_since(&headers, date)),
+    };
+    let etag = etag.map(|etag| [(ETAG, etag)]);
+
+    if fresh {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            cache_control,
+            last_modified,
+            etag,
+            (),
+        )
+            .into_response());
+    }
+
+    match post {
         ReturnedPost::Rendered {
             ref meta,
             body,
             perf,
             raw_name,
+            ..
         } => {
             let joined_tags = meta.tags.join(", ");
+            let (canonical_url, image_url) = {
+                let link = &rss.load().link;
+                let base_path = base_path.load();
+                let canonical_url =
+                    link.join(&format!("{}/posts/{}", *base_path, meta.url_name()))?;
+                let image_url = match &meta.icon {
+                    Some(icon) => Some(link.join(&with_base_path(&base_path, icon))?.to_string()),
+                    None => None,
+                };
+                (canonical_url.to_string(), image_url)
+            };
+            let json_ld = JsonLd {
+                context: "https://schema.org",
+                type_: "BlogPosting",
+                headline: &meta.title,
+                description: &meta.description,
+                author: JsonLdPerson {
+                    type_: "Person",
+                    name: &meta.author,
+                },
+                date_published: meta.written_at,
+                date_modified: meta.modified_at,
+                keywords: &joined_tags,
+                url: &canonical_url,
+                image: image_url.as_deref(),
+            }
+            .to_script()
+            .map_err(PostError::from)?;
+
+            let site = site_context(listed.len(), &rss, &base_path);
 
             let reg = templates.read().await;
+            let template = match meta.template.as_deref() {
+                Some(name) if reg.has_template(name) => name,
+                Some(name) => {
+                    warn!(
+                        "post {:?} asks for unknown template {name:?}, using \"post\"",
+                        meta.name
+                    );
+                    "post"
+                }
+                None => "post",
+            };
             let style = style.load();
+            let color = meta.color.as_deref().or(style.default_color.as_deref());
             let rendered = reg.render(
-                "post",
-                &PostTemplate {
-                    bingus_info: &BINGUS_INFO,
-                    meta,
-                    body,
-                    perf,
-                    js: style.js_enable,
-                    color: meta.color.as_deref().or(style.default_color.as_deref()),
-                    joined_tags,
-                    style: &style,
-                    raw_name,
+                template,
+                &WithSite {
+                    site: &site,
+                    page: &PostTemplate {
+                        bingus_info: &BINGUS_INFO,
+                        meta,
+                        mermaid: body.contains(r#"<pre class="mermaid">"#),
+                        body,
+                        perf,
+                        js: style.js_enable,
+                        color,
+                        theme: color.and_then(Theme::from_color),
+                        canonical_url,
+                        image_url,
+                        json_ld,
+                        joined_tags,
+                        style: &style,
+                        raw_name,
+                        related: linked.related,
+                        series_prev: linked.series_prev,
+                        series_next: linked.series_next,
+                    },
                 },
             );
             drop((style, reg));
 
-            Ok(Html(rendered?).into_response())
+            Ok((cache_control, last_modified, etag, Html(rendered?)).into_response())
         }
         ReturnedPost::Raw {
-            buffer,
+            body,
+            length,
             content_type,
-        } => Ok(([(CONTENT_TYPE, content_type)], buffer).into_response()),
+            ..
+        } => Ok((
+            cache_control,
+            last_modified,
+            length.map(|length| [(CONTENT_LENGTH, length)]),
+            [(CONTENT_TYPE, content_type)],
+            body,
+        )
+            .into_response()),
     }
 }
 
-pub fn new(dirs: &DirsConfig) -> Router<AppState> {
-    Router::new()
-        .route("/", get(index))
-        .route(
-            "/post/{name}",
-            get(|Path(name): Path<String>| async move { Redirect::to(&format!("/posts/{name}")) }),
+#[derive(Serialize)]
+struct ErrorJson<'a> {
+    error: &'a str,
+}
+
+/// renders error responses as JSON if the client prefers it, or with the
+/// `404` template if they're not found errors
+async fn render_error(
+    State(AppState {
+        rss,
+        base_path,
+        listed_posts,
+        templates,
+        style,
+        ..
+    }): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let json = prefers_json(request.headers());
+    let mut response = next.run(request).await;
+    let Some(ErrorMessage(message)) = response.extensions().get::<ErrorMessage>() else {
+        return response;
+    };
+    let status = response.status();
+    let vary = [(VARY, "Accept")];
+
+    if json {
+        // keep headers like Retry-After and the CORS ones
+        let message = message.clone();
+        let (mut parts, _) = response.into_parts();
+        parts
+            .headers
+            .append(VARY, HeaderValue::from_static("Accept"));
+        return (parts, Json(ErrorJson { error: &message })).into_response();
+    }
+    if status != StatusCode::NOT_FOUND {
+        response
+            .headers_mut()
+            .append(VARY, HeaderValue::from_static("Accept"));
+        return response;
+    }
+
+    // only the last count, a bad url shouldn't make the server go through
+    // every post
+    let site = site_context(listed_posts.last().len(), &rss, &base_path);
+
+    let reg = templates.read().await;
+    let style = style.load();
+    let rendered = reg.render(
+        "404",
+        &WithSite {
+            site: &site,
+            page: &NotFoundTemplate {
+                bingus_info: &BINGUS_INFO,
+                message,
+                style: &style,
+            },
+        },
+    );
+    drop((style, reg));
+
+    match rendered {
+        Ok(rendered) => (status, vary, Html(rendered)).into_response(),
+        Err(err) => {
+            error!("error while rendering 404 template: {err}");
+            response
+        }
+    }
+}
+
+/// `Cache-Control` for successful responses from a directory service
+fn asset_cache_control<B>(
+    cache_control: Arc<dyn DynAccess<CacheControlConfig> + Send + Sync>,
+    pick: fn(&CacheControlConfig) -> &Option<Box<str>>,
+) -> impl FnMut(&Response<B>) -> Option<HeaderValue> + Clone {
+    move |response: &Response<B>| {
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+            return None;
+        }
+        pick(&cache_control.load())
+            .as_deref()
+            .and_then(|value| HeaderValue::from_str(value).ok())
+    }
+}
+
+fn cors_layer(origins: &[String]) -> Option<CorsLayer> {
+    if origins.is_empty() {
+        return None;
+    }
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        // validated when the config was loaded
+        AllowOrigin::list(
+            origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
         )
-        .route("/posts/{name}", get(post))
-        .route("/posts", get(posts))
+    };
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::HEAD]),
+    )
+}
+
+pub fn new(config: &Config, state: AppState) -> Router {
+    let dirs = &config.dirs;
+
+    let mut api = Router::new()
         .route("/posts.json", get(posts_json))
-        .route("/feed.xml", get(rss))
+        .route("/search", get(search))
+        .route("/feed.xml", get(rss));
+    if let Some(cors) = cors_layer(&config.http.cors_allowed_origins) {
+        api = api.layer(cors);
+    }
+
+    let mut router = Router::new()
+        .route("/", get(index))
+        .route("/post/{*name}", get(post_redirect))
+        .route("/posts/{*name}", get(post))
+        .route("/authors/{author}", get(author))
+        .route("/posts", get(posts))
+        .route("/tags", get(tags))
+        .route("/tags/{tag}", get(tag))
+        .route("/series/{name}", get(series))
+        .merge(api);
+
+    // the cheap routes below aren't limited, neither are static files
+    if config.http.rate_limit.enable {
+        let limiter = Arc::new(RateLimiter::new(&config.http.rate_limit));
+        router = router.layer(middleware::from_fn_with_state(limiter, rate_limit::limit));
+    }
+
+    router = router
+        .route("/robots.txt", get(robots))
+        .route("/opensearch.xml", get(opensearch))
+        .route("/healthz", get(healthz))
+        .route("/metrics", get(metrics))
+        .route("/admin/cache/purge", routing::post(purge_cache))
+        .route("/admin/cache/stats", get(cache_stats));
+
+    // only the routes so far, static files are precompressed instead
+    if config.http.compression {
+        router = router.layer(CompressionLayer::new());
+    }
+
+    router
         .nest_service(
             "/static",
-            ServeDir::new(&dirs.static_)
-                .precompressed_gzip()
-                .fallback(service_fn(|req| handle(req, &STATIC))),
+            // the included files set their own, long-lived Cache-Control
+            SetResponseHeader::if_not_present(
+                dirs.precompressed
+                    .iter()
+                    .fold(
+                        ServeDir::new(&dirs.static_),
+                        |serve_dir, format| match format {
+                            Precompressed::Br => serve_dir.precompressed_br(),
+                            Precompressed::Gzip => serve_dir.precompressed_gzip(),
+                            Precompressed::Zstd => serve_dir.precompressed_zstd(),
+                            Precompressed::Deflate => serve_dir.precompressed_deflate(),
+                        },
+                    )
+                    .fallback(service_fn(|req| handle(req, &STATIC))),
+                CACHE_CONTROL,
+                asset_cache_control(state.cache_control.clone(), |c| &c.static_),
+            ),
         )
-        .nest_service("/media", ServeDir::new(&dirs.media))
+        .nest_service(
+            "/media",
+            SetResponseHeader::if_not_present(
+                ServeDir::new(&dirs.media),
+                CACHE_CONTROL,
+                asset_cache_control(state.cache_control.clone(), |c| &c.media),
+            ),
+        )
+        .fallback(|| async { AppError::NotFound })
+        .layer(middleware::from_fn_with_state(state.clone(), render_error))
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(|request: &Request<_>| {
@@ -354,4 +1565,493 @@ pub fn new(dirs: &DirsConfig) -> Router<AppState> {
                     info!(?status, ?duration, "response");
                 }),
         )
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+    use axum::http::header::{ACCEPT_RANGES, CONTENT_RANGE, RANGE};
+    use axum::http::Uri;
+    use chrono::TimeZone;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::post::PostFilter;
+
+    fn max_posts(max: usize) -> Arc<dyn DynAccess<usize> + Send + Sync> {
+        Arc::new(ArcSwap::from_pointee(max))
+    }
+
+    #[test]
+    fn n_is_clamped() {
+        let max_posts = max_posts(10);
+
+        assert_eq!(limit(Some(1_000_000), &max_posts), Some(10));
+        assert_eq!(limit(Some(3), &max_posts), Some(3));
+        assert_eq!(limit(None, &max_posts), None);
+    }
+
+    #[test]
+    fn n_is_validated() {
+        let query = |uri: &'static str| Query::<QueryParams>::try_from_uri(&Uri::from_static(uri));
+
+        assert_eq!(query("/?n=5").unwrap().num_posts, Some(5));
+        assert!(query("/?n=-1").is_err());
+        assert!(query("/?n=lots").is_err());
+    }
+
+    /// the whole app, serving from `root`
+    async fn app(root: &std::path::Path, configure: impl FnOnce(&mut Config)) -> Router {
+        app_with_state(root, configure).await.0
+    }
+
+    /// [`app`], with its state
+    async fn app_with_state(
+        root: &std::path::Path,
+        configure: impl FnOnce(&mut Config),
+    ) -> (Router, AppState) {
+        let mut config = Config::default();
+        configure(&mut config);
+        config.engine.markdown.root = root.join("posts").into();
+        config.dirs.media = root.join("media").into();
+        // missing, so static files come from the included ones
+        config.dirs.static_ = root.join("static").into();
+        config.dirs.templates = root.join("templates").into();
+        let swapper = Arc::new(ArcSwap::from_pointee(config));
+
+        let config = swapper.load();
+        let posts = crate::new_posts(&config, &swapper, None).await.unwrap();
+        let templates = crate::new_templates(&config, &swapper).unwrap();
+        let state = crate::new_state(&swapper, posts, Arc::new(RwLock::new(templates)), None);
+        (new(&config, state.clone()), state)
+    }
+
+    #[tokio::test]
+    async fn media_range() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-media-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("media")).unwrap();
+        std::fs::write(root.join("media/bingus.txt"), "0123456789").unwrap();
+        let app = app(&root, |_| {}).await;
+
+        let request = |range: &str| {
+            Request::get("/media/bingus.txt")
+                .header(RANGE, range)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request("bytes=2-5")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes 2-5/10");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"2345");
+
+        let response = app.clone().oneshot(request("bytes=-3")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes 7-9/10");
+
+        let response = app.oneshot(request("bytes=20-30")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes */10");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn included_static_range() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-static-{}", std::process::id()));
+        let app = app(&root, |_| {}).await;
+        let len = STATIC.get_file("style.css").unwrap().contents().len();
+
+        let response = app
+            .oneshot(
+                Request::get("/static/style.css")
+                    .header(RANGE, "bytes=0-3")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+        assert_eq!(
+            response.headers()[CONTENT_RANGE],
+            format!("bytes 0-3/{len}").as_str()
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn post_redirect_keeps_base_path() {
+        let root =
+            std::env::temp_dir().join(format!("bingus-blog-redirect-{}", std::process::id()));
+        let app = app(&root, |config| config.http.base_path = "/blog".into()).await;
+
+        let response = app
+            .oneshot(Request::get("/post/hello").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.status().is_redirection());
+        assert_eq!(response.headers()[LOCATION], "/blog/posts/hello");
+    }
+
+    #[tokio::test]
+    async fn robots_sitemap_is_absolute() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-robots-{}", std::process::id()));
+        let robots = |app: Router| async move {
+            let response = app
+                .oneshot(Request::get("/robots.txt").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        let body = robots(app(&root, |_| {}).await).await;
+        assert!(!body.contains("Sitemap:"));
+
+        let body = robots(
+            app(&root, |config| {
+                config.rss.enable = true;
+                config.rss.link = "https://example.com/".parse().unwrap();
+                config.http.base_path = "/blog".into();
+            })
+            .await,
+        )
+        .await;
+        assert!(body.contains("Sitemap: https://example.com/blog/feed.xml\n"));
+
+        let body = robots(
+            app(&root, |config| {
+                config.rss.link = "https://example.com/".parse().unwrap();
+                config.robots.sitemap = Some("/sitemap.xml".into());
+            })
+            .await,
+        )
+        .await;
+        assert!(body.contains("Sitemap: https://example.com/sitemap.xml\n"));
+    }
+
+    #[tokio::test]
+    async fn mermaid_only_on_diagrams() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-mermaid-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("posts")).unwrap();
+        std::fs::write(
+            root.join("posts/diagram.md"),
+            "---\ntitle: diagram\nauthor: bingus\n---\n\n```mermaid\ngraph TD; A-->B\n```\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("posts/plain.md"),
+            "---\ntitle: plain\nauthor: bingus\n---\n\nno diagrams\n",
+        )
+        .unwrap();
+        let app = app(&root, |config| {
+            config.engine.markdown.render.mermaid = true;
+            config.style.mermaid_url = "/static/mermaid/mermaid.esm.min.mjs".into();
+        })
+        .await;
+        let page = |name: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(Request::get(name).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        let diagram = page("/posts/diagram").await;
+        assert!(diagram.contains(r#"data-mermaid="/static/mermaid/mermaid.esm.min.mjs""#));
+        assert!(!page("/posts/plain").await.contains("mermaid.js"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn raw_front_matter_overrides_raw_access() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-raw-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("posts")).unwrap();
+        std::fs::write(
+            root.join("posts/hidden.md"),
+            "---\ntitle: hidden\nauthor: bingus\nraw: false\n---\n\nsecret source\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("posts/shown.md"),
+            "---\ntitle: shown\nauthor: bingus\nraw: true\n---\n\nopen source\n",
+        )
+        .unwrap();
+        let status = |app: Router, uri: &'static str| async move {
+            app.oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status()
+        };
+
+        for raw_access in [true, false] {
+            let app = app(&root, |config| {
+                config.engine.markdown.raw_access = raw_access
+            })
+            .await;
+            let hidden = status(app.clone(), "/posts/hidden.md").await;
+            assert_eq!(hidden, StatusCode::NOT_FOUND, "raw_access = {raw_access}");
+            let shown = status(app, "/posts/shown.md").await;
+            assert_eq!(shown, StatusCode::OK, "raw_access = {raw_access}");
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn alias_redirects() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-alias-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("posts")).unwrap();
+        std::fs::write(
+            root.join("posts/new-name.md"),
+            "---\ntitle: moved\nauthor: bingus\naliases: [old-name]\n---\n\nhello\n",
+        )
+        .unwrap();
+        let app = app(&root, |config| config.http.base_path = "/blog".into()).await;
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/posts/old-name").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[LOCATION], "/blog/posts/new-name");
+
+        let response = app
+            .oneshot(
+                Request::get("/posts/never-was")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn feed_etag_changes() {
+        let post = |name: &str, year: i32, content_hash: &str| {
+            let mut metadata = crate::post::tests::metadata(name, &[]);
+            metadata.written_at = Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).single();
+            metadata.content_hash = content_hash.into();
+            metadata
+        };
+        let rss = RssConfig::default();
+        let style = StyleConfig::default();
+        let etag =
+            |posts: &[PostMetadata], rss: &RssConfig| feed_etag(posts.to_vec(), rss, &style, "");
+        let posts = [post("a", 2024, "1"), post("b", 2023, "1")];
+        let base = etag(&posts, &rss);
+
+        // order doesn't matter
+        assert_eq!(etag(&[posts[1].clone(), posts[0].clone()], &rss), base);
+        // an edit that keeps the dates
+        assert_ne!(
+            etag(&[post("a", 2024, "2"), post("b", 2023, "1")], &rss),
+            base
+        );
+        // swapped for an older post
+        assert_ne!(
+            etag(&[post("a", 2024, "1"), post("c", 2022, "1")], &rss),
+            base
+        );
+
+        let full_content = RssConfig {
+            full_content: !rss.full_content,
+            ..Default::default()
+        };
+        assert_ne!(etag(&posts, &full_content), base);
+
+        // only the newest post is in the feed, the rest don't count
+        let max_items = RssConfig {
+            max_items: Some(1),
+            ..Default::default()
+        };
+        assert_ne!(etag(&posts, &max_items), base);
+        assert_eq!(
+            etag(&[post("a", 2024, "1"), post("c", 2022, "2")], &max_items),
+            etag(&posts, &max_items)
+        );
+    }
+
+    #[tokio::test]
+    async fn feed_not_modified() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-feed-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("posts")).unwrap();
+        let write = |body: &str| {
+            // pinned dates, so only the body changes
+            let post = format!(
+                "---\ntitle: post\nauthor: bingus\nwritten_at: 2024-01-01T00:00:00Z\n\
+                 modified_at: 2024-01-02T00:00:00Z\n---\n\n{body}\n"
+            );
+            std::fs::write(root.join("posts/post.md"), post).unwrap();
+        };
+        write("first");
+        let app = app(&root, |config| config.rss.enable = true).await;
+        let get = |etag: Option<HeaderValue>| {
+            let mut request = Request::get("/feed.xml");
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let response = get(None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[ETAG].clone();
+
+        let response = get(Some(etag.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        write("edited");
+        let response = get(Some(etag.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[ETAG], etag);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// counts how many times the posts are listed
+    #[derive(Default)]
+    struct CountingPosts {
+        listed: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PostManager for CountingPosts {
+        async fn get_all_posts(
+            &self,
+            _filters: &[Box<dyn PostFilter + '_>],
+            _query: &IndexMap<String, Value>,
+        ) -> Result<Vec<(PostMetadata, Arc<str>, RenderStats)>, PostError> {
+            self.listed.fetch_add(1, Ordering::Relaxed);
+            Ok(["a", "b"]
+                .map(|name| {
+                    let metadata = crate::post::tests::metadata(name, &[]);
+                    (metadata, "".into(), RenderStats::Unknown)
+                })
+                .into())
+        }
+
+        async fn get_post(
+            &self,
+            name: Arc<str>,
+            _query: &IndexMap<String, Value>,
+            _headers: &HeaderMap,
+        ) -> Result<ReturnedPost, PostError> {
+            Err(PostError::NotFound(name))
+        }
+    }
+
+    #[tokio::test]
+    async fn listed_posts_are_reused() {
+        let posts = CountingPosts::default();
+        let listed = ListedPosts::default();
+
+        for _ in 0..3 {
+            assert_eq!(listed.get(&posts).await.len(), 2);
+        }
+        assert_eq!(listed.last().len(), 2);
+        assert_eq!(posts.listed.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn series_links_change_validators() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-series-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("posts")).unwrap();
+        let write = |name: &str, index: u32| {
+            let post = format!(
+                "---\ntitle: part {index}\nauthor: bingus\nseries: saga\nseries_index: {index}\n\
+                 modified_at: 2024-01-01T00:00:00Z\n---\n\npart {index}\n"
+            );
+            std::fs::write(root.join("posts").join(name), post).unwrap();
+        };
+        write("part-1.md", 1);
+        let (app, state) = app_with_state(&root, |_| {}).await;
+        let get = |headers: &[(HeaderName, HeaderValue)]| {
+            let mut request = Request::get("/posts/part-1");
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+        let since = HeaderValue::from_static("Tue, 02 Jan 2024 00:00:00 GMT");
+
+        let response = get(&[]).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(LAST_MODIFIED));
+        let etag = response.headers()[ETAG].clone();
+        let response = get(&[(IF_NONE_MATCH, etag.clone())]).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // part 2 comes out, and the listed posts are next listed
+        write("part-2.md", 2);
+        *state.listed_posts.listed_at.lock().await = None;
+
+        let response = get(&[(IF_NONE_MATCH, etag.clone())]).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[ETAG], etag);
+        assert!(!response.headers().contains_key(LAST_MODIFIED));
+        let response = get(&[(IF_MODIFIED_SINCE, since)]).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn related_posts_change_validators() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-related-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("posts")).unwrap();
+        let write = |name: &str| {
+            let post = format!("---\ntitle: {name}\nauthor: bingus\ntags: [rust]\n---\n\n{name}\n");
+            std::fs::write(root.join("posts").join(format!("{name}.md")), post).unwrap();
+        };
+        write("first");
+        let (app, state) = app_with_state(&root, |_| {}).await;
+        let get = |etag: Option<HeaderValue>| {
+            let mut request = Request::get("/posts/first");
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let response = get(None).await.unwrap();
+        let etag = response.headers()[ETAG].clone();
+        let response = get(Some(etag.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // a post sharing a tag comes out, and the listed posts are next listed
+        write("second");
+        *state.listed_posts.listed_at.lock().await = None;
+
+        let response = get(Some(etag.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[ETAG], etag);
+        assert!(!response.headers().contains_key(LAST_MODIFIED));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::DateTime;
+use color_eyre::eyre::{self, bail, Context};
+use serde::Serialize;
+
+use crate::config::CacheConfig;
+use crate::post::cache::load_cache;
+
+const USAGE: &str = "usage: bingus-blog cache-inspect [--json] [FILE]";
+
+#[derive(Serialize)]
+struct Entry {
+    name: Arc<str>,
+    render_hash: u64,
+    /// seconds since epoch
+    mtime: u64,
+    /// milliseconds since epoch
+    cached_at: u128,
+    /// milliseconds since epoch
+    last_accessed: u128,
+    body_bytes: usize,
+}
+
+#[derive(Serialize)]
+struct Report {
+    version: u16,
+    entries: Vec<Entry>,
+}
+
+fn format_millis(millis: u128) -> String {
+    i64::try_from(millis)
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+        .map_or_else(|| millis.to_string(), |date| date.to_rfc3339())
+}
+
+fn print_table(report: &Report) {
+    println!(
+        "cache version {}, {} entries",
+        report.version,
+        report.entries.len()
+    );
+    if report.entries.is_empty() {
+        return;
+    }
+
+    let width = report
+        .entries
+        .iter()
+        .map(|entry| entry.name.len())
+        .max()
+        .unwrap_or_default()
+        .max("NAME".len());
+    println!(
+        "{:width$}  {:16}  {:25}  {:29}  {:29}  BODY BYTES",
+        "NAME", "RENDER HASH", "MTIME", "CACHED AT", "LAST ACCESSED"
+    );
+    for entry in &report.entries {
+        let mtime = i64::try_from(entry.mtime)
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .map_or_else(|| entry.mtime.to_string(), |date| date.to_rfc3339());
+        println!(
+            "{:width$}  {:016x}  {:25}  {:29}  {:29}  {}",
+            entry.name,
+            entry.render_hash,
+            mtime,
+            format_millis(entry.cached_at),
+            format_millis(entry.last_accessed),
+            entry.body_bytes,
+        );
+    }
+}
+
+/// prints what's in a persisted cache file. compression is detected from
+/// the file, so it doesn't need to match the config
+pub async fn run(args: Vec<String>) -> eyre::Result<()> {
+    let mut json = false;
+    let mut file = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if arg.starts_with('-') || file.is_some() => bail!("{USAGE}"),
+            _ => file = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let mut config = CacheConfig::default();
+    if let Some(file) = file {
+        config.file = file.into();
+    }
+    let cache = load_cache(&config)
+        .await
+        .with_context(|| format!("failed to load cache from {:?}", config.file))?;
+
+    let mut entries: Vec<Entry> = cache
+        .entries()
+        .await
+        .into_iter()
+        .map(|(key, value)| Entry {
+            name: key.name,
+            render_hash: key.extra,
+            mtime: value.mtime,
+            cached_at: value.cached_at,
+            last_accessed: value.last_accessed,
+            body_bytes: value.body.len(),
+        })
+        .collect();
+    entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    let report = Report {
+        version: cache.version(),
+        entries,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_table(&report);
+    }
+    Ok(())
+}
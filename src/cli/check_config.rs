@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use color_eyre::eyre::{self, bail};
+
+use crate::config;
+use crate::validate::{check_templates, Check, RELOAD_CHECKS};
+
+const USAGE: &str = "usage: bingus-blog check-config [FILE]";
+
+/// loads a config and checks the parts of it that would only fail once the
+/// server is running
+pub async fn run(args: Vec<String>) -> eyre::Result<()> {
+    let mut file = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if arg.starts_with('-') || file.is_some() => bail!("{USAGE}"),
+            _ => file = Some(arg),
+        }
+    }
+    let file = file.map_or_else(config::config_path, Into::into);
+
+    // load_from would write a default config in its place
+    if !Path::new(&*file).is_file() {
+        bail!("{file:?} doesn't exist");
+    }
+    let config = config::load_from(&*file).await?;
+    println!("ok    config: parsed {file:?}");
+
+    let checks: Vec<(&str, Check)> = [("templates", check_templates as Check)]
+        .into_iter()
+        .chain(RELOAD_CHECKS)
+        .collect();
+    let mut failed = 0;
+    for &(name, check) in &checks {
+        match check(&config) {
+            Ok(status) => println!("ok    {name}: {status}"),
+            Err(err) => {
+                failed += 1;
+                println!("FAIL  {name}: {err:#}");
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {} checks failed", checks.len() + 1);
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use axum::body::{to_bytes, Body};
+use axum::http::Request;
+use axum::Router;
+use color_eyre::eyre::{self, bail, Context};
+use indexmap::IndexMap;
+use tokio::sync::RwLock;
+use tower::ServiceExt;
+use url::Url;
+
+use crate::app::{self, STATIC};
+use crate::config::{self, parse_base_path, EngineMode};
+use crate::post::{listing_filters, ListingQuery};
+use crate::{new_posts, new_state, new_templates};
+
+const USAGE: &str = "usage: bingus-blog export [--base-path PATH] OUT";
+
+/// renders a page through the app and writes it to `out/file`
+async fn export_page(app: &Router, out: &Path, segments: &[&str], file: &str) -> eyre::Result<()> {
+    let mut url = Url::parse("http://export/").expect("url to be valid");
+    url.path_segments_mut()
+        .expect("url to be a base")
+        .pop_if_empty()
+        .extend(segments);
+    let path = url.path();
+
+    let request = Request::get(path).body(Body::empty())?;
+    let response = app.clone().oneshot(request).await?;
+    if !response.status().is_success() {
+        bail!("{path} returned {}", response.status());
+    }
+    let body = to_bytes(response.into_body(), usize::MAX).await?;
+
+    // tags and authors come straight from front matter
+    if !Path::new(file)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("refusing to write {file:?} outside of {out:?}");
+    }
+    let file = out.join(file);
+    if let Some(parent) = file.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&file, body)
+        .await
+        .with_context(|| format!("failed to write {file:?}"))?;
+    println!("{path} -> {}", file.display());
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let to = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to)?;
+        } else {
+            std::fs::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+/// renders every public page of the blog to static files in `OUT`, which can
+/// then be served by anything that resolves `/posts/foo` to `posts/foo.html`
+pub async fn run(args: Vec<String>) -> eyre::Result<()> {
+    let mut base_path = None;
+    let mut out = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--base-path" => {
+                let Some(path) = args.next() else {
+                    bail!("{USAGE}");
+                };
+                base_path = Some(parse_base_path(&path).map_err(|err| eyre::eyre!(err))?);
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if arg.starts_with('-') || out.is_some() => bail!("{USAGE}"),
+            _ => out = Some(PathBuf::from(arg)),
+        }
+    }
+    let Some(out) = out else {
+        bail!("{USAGE}");
+    };
+
+    let (mut config, _) = config::load()
+        .await
+        .context("couldn't load configuration")?;
+    if let Some(base_path) = base_path {
+        config.http.base_path = base_path;
+    }
+    let swapper = Arc::new(ArcSwap::from_pointee(config));
+    let config = swapper.load_full();
+
+    let templates = Arc::new(RwLock::new(new_templates(&config, &swapper)?));
+    // everything is rendered once, so there's nothing to cache
+    let posts = new_posts(&config, &swapper, None).await?;
+    let state = new_state(&swapper, posts.clone(), templates, None);
+    let app = app::new(&config, state);
+
+    let listing = ListingQuery::default();
+    let metadata = posts
+        .get_all_post_metadata(&listing_filters(&listing), &IndexMap::new())
+        .await?;
+
+    export_page(&app, &out, &[], "index.html").await?;
+    export_page(&app, &out, &["posts"], "posts.html").await?;
+    export_page(&app, &out, &["posts.json"], "posts.json").await?;
+    export_page(&app, &out, &["tags"], "tags.html").await?;
+    export_page(&app, &out, &["robots.txt"], "robots.txt").await?;
+    if config.rss.enable {
+        export_page(&app, &out, &["feed.xml"], "feed.xml").await?;
+    }
+
+    let mut tags: Vec<&str> = metadata
+        .iter()
+        .flat_map(|meta| meta.tags.iter().map(|tag| &**tag))
+        .collect();
+    let mut authors: Vec<&str> = metadata.iter().map(|meta| &*meta.author).collect();
+    let mut series: Vec<&str> = metadata
+        .iter()
+        .filter_map(|meta| meta.series.as_deref())
+        .collect();
+    for list in [&mut tags, &mut authors, &mut series] {
+        list.sort_unstable();
+        list.dedup();
+    }
+    for tag in tags {
+        export_page(&app, &out, &["tags", tag], &format!("tags/{tag}.html")).await?;
+    }
+    for author in authors {
+        let file = format!("authors/{author}.html");
+        export_page(&app, &out, &["authors", author], &file).await?;
+    }
+    for name in series {
+        export_page(
+            &app,
+            &out,
+            &["series", name],
+            &format!("series/{name}.html"),
+        )
+        .await?;
+    }
+
+    let (raw_access, raw_ext) = match config.engine.mode {
+        EngineMode::Markdown => (config.engine.markdown.raw_access, "md"),
+        EngineMode::Blag => (config.engine.blag.raw_access, "sh"),
+    };
+    for meta in &metadata {
+        let name = meta.url_name();
+        let segments: Vec<&str> = ["posts"].into_iter().chain(name.split('/')).collect();
+        export_page(&app, &out, &segments, &format!("posts/{name}.html")).await?;
+        // what the "view raw" link points to
+        if meta.raw_access.unwrap_or(raw_access) {
+            let raw = format!("{}.{raw_ext}", meta.name);
+            let segments: Vec<&str> = ["posts"].into_iter().chain(raw.split('/')).collect();
+            export_page(&app, &out, &segments, &format!("posts/{raw}")).await?;
+        }
+    }
+
+    // the embedded files first, so the ones in the static directory override them
+    let static_ = out.join("static");
+    std::fs::create_dir_all(&static_)?;
+    STATIC
+        .extract(&static_)
+        .with_context(|| format!("failed to extract static files to {static_:?}"))?;
+    for (from, to) in [
+        (&config.dirs.static_, static_),
+        (&config.dirs.media, out.join("media")),
+    ] {
+        if from.exists() {
+            copy_dir(from, &to).with_context(|| format!("failed to copy {from:?} to {to:?}"))?;
+        }
+    }
+
+    println!("exported {} posts to {}", metadata.len(), out.display());
+    Ok(())
+}
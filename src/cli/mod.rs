@@ -0,0 +1,23 @@
+//! subcommands for working with a blog without starting the server
+
+mod cache_inspect;
+mod check_config;
+mod export;
+mod new_post;
+
+use color_eyre::eyre;
+
+/// runs the subcommand named by the first argument, `None` if there isn't one
+pub async fn run() -> Option<eyre::Result<()>> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next()?;
+    let args = args.collect();
+
+    Some(match command.as_str() {
+        "cache-inspect" => cache_inspect::run(args).await,
+        "check-config" => check_config::run(args).await,
+        "export" => export::run(args).await,
+        "new-post" => new_post::run(args).await,
+        _ => return None,
+    })
+}
@@ -0,0 +1,105 @@
+use std::env;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, SubsecRound, Utc};
+use color_eyre::eyre::{self, bail, Context};
+use const_str::{concat, convert_ascii_case};
+use serde::Serialize;
+
+use crate::text::slugify;
+
+const USAGE: &str = "usage: bingus-blog new-post [--author AUTHOR] [--tags TAG,...] [--root DIR] [--force] TITLE...";
+const AUTHOR_VAR: &str = concat!(
+    convert_ascii_case!(shouty_snake, env!("CARGO_BIN_NAME")),
+    "_AUTHOR"
+);
+
+/// the subset of the markdown front matter a new post starts with
+#[derive(Serialize)]
+struct FrontMatter<'a> {
+    title: &'a str,
+    author: &'a str,
+    written_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tags: &'a [String],
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>) -> eyre::Result<String> {
+    match args.next() {
+        Some(value) => Ok(value),
+        None => bail!("{USAGE}"),
+    }
+}
+
+/// writes `<root>/<slug>.md` with a front matter block filled in
+pub async fn run(args: Vec<String>) -> eyre::Result<()> {
+    let mut author = None;
+    let mut tags = Vec::new();
+    let mut root = PathBuf::from("posts");
+    let mut force = false;
+    let mut title = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--author" => author = Some(next_value(&mut args)?),
+            "--tags" => tags.extend(
+                next_value(&mut args)?
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(String::from),
+            ),
+            "--root" => root = next_value(&mut args)?.into(),
+            "--force" => force = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if arg.starts_with('-') => bail!("{USAGE}"),
+            _ => title.push(arg),
+        }
+    }
+
+    let title = title.join(" ");
+    if title.trim().is_empty() {
+        bail!("{USAGE}");
+    }
+    let slug = slugify(&title);
+    if slug.is_empty() {
+        bail!("can't make a file name out of {title:?}");
+    }
+    let Some(author) = author.or_else(|| env::var(AUTHOR_VAR).ok()) else {
+        bail!("no author given, pass --author or set {AUTHOR_VAR}");
+    };
+
+    let front_matter = serde_yaml::to_string(&FrontMatter {
+        title: &title,
+        author: &author,
+        written_at: Utc::now().trunc_subsecs(0),
+        tags: &tags,
+    })?;
+    // left commented out so the first paragraph is used until it's filled in
+    let contents = format!("---\n{front_matter}# description: \n---\n\n");
+
+    let path = root.join(slug + ".md");
+    let mut file = match std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!force)
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            bail!("{path:?} already exists, pass --force to overwrite it")
+        }
+        Err(err) => return Err(err).with_context(|| format!("failed to create {path:?}")),
+    };
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("failed to write to {path:?}"))?;
+
+    println!("{}", path.display());
+    Ok(())
+}
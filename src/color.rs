@@ -0,0 +1,141 @@
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// parses `#rgb` and `#rrggbb` colors
+    pub fn parse(color: &str) -> Option<Self> {
+        let hex = color.strip_prefix('#')?;
+        if !hex.is_ascii() {
+            return None;
+        }
+
+        let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+        let short = |s: &str| channel(s).map(|v| v * 17);
+        match hex.len() {
+            3 => Some(Self(
+                short(&hex[0..1])?,
+                short(&hex[1..2])?,
+                short(&hex[2..3])?,
+            )),
+            6 => Some(Self(
+                channel(&hex[0..2])?,
+                channel(&hex[2..4])?,
+                channel(&hex[4..6])?,
+            )),
+            _ => None,
+        }
+    }
+
+    /// relative luminance as defined by WCAG 2
+    pub fn luminance(self) -> f64 {
+        let linear = |c: u8| {
+            let c = c as f64 / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.0) + 0.7152 * linear(self.1) + 0.0722 * linear(self.2)
+    }
+
+    pub fn contrast(self, other: Self) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// mixes `amount` of `other` into this color
+    pub fn mix(self, other: Self, amount: f64) -> Self {
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * amount).round() as u8;
+        Self(
+            mix(self.0, other.0),
+            mix(self.1, other.1),
+            mix(self.2, other.2),
+        )
+    }
+
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+const BLACK: Rgb = Rgb(0, 0, 0);
+const WHITE: Rgb = Rgb(255, 255, 255);
+const GRAY: Rgb = Rgb(128, 128, 128);
+
+/// shades derived from a single accent color, for templates to theme with
+#[derive(Serialize, Debug)]
+pub struct Theme {
+    pub accent: String,
+    /// black or white, whichever reads better on the accent
+    pub foreground: String,
+    /// the accent, desaturated
+    pub muted: String,
+    /// the accent, darkened
+    pub border: String,
+}
+
+impl Theme {
+    pub fn from_color(color: &str) -> Option<Self> {
+        let accent = Rgb::parse(color)?;
+        let foreground = if accent.contrast(BLACK) >= accent.contrast(WHITE) {
+            BLACK
+        } else {
+            WHITE
+        };
+
+        Some(Self {
+            accent: accent.to_hex(),
+            foreground: foreground.to_hex(),
+            muted: accent.mix(GRAY, 0.5).to_hex(),
+            border: accent.mix(BLACK, 0.25).to_hex(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Rgb::parse("#fa0"), Some(Rgb(255, 170, 0)));
+        assert_eq!(Rgb::parse("#ffaa00"), Some(Rgb(255, 170, 0)));
+        assert_eq!(Rgb::parse("ffaa00"), None);
+        assert_eq!(Rgb::parse("#ffaa0"), None);
+        assert_eq!(Rgb::parse("#ggg"), None);
+        assert_eq!(Rgb::parse("#ffé"), None);
+    }
+
+    #[test]
+    fn contrast() {
+        assert_eq!(BLACK.contrast(WHITE), 21.0);
+        assert_eq!(WHITE.contrast(WHITE), 1.0);
+    }
+
+    #[test]
+    fn from_color() {
+        let white = Theme::from_color("#fff").unwrap();
+        assert_eq!(white.accent, "#ffffff");
+        assert_eq!(white.foreground, "#000000");
+        assert_eq!(white.muted, "#c0c0c0");
+        assert_eq!(white.border, "#bfbfbf");
+
+        let black = Theme::from_color("#000000").unwrap();
+        assert_eq!(black.foreground, "#ffffff");
+        assert_eq!(black.muted, "#404040");
+        assert_eq!(black.border, "#000000");
+
+        let red = Theme::from_color("#f00").unwrap();
+        assert_eq!(red.foreground, "#000000");
+        assert_eq!(red.muted, "#c04040");
+        assert_eq!(red.border, "#bf0000");
+
+        let navy = Theme::from_color("#000080").unwrap();
+        assert_eq!(navy.foreground, "#ffffff");
+
+        assert!(Theme::from_color("red").is_none());
+    }
+}
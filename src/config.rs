@@ -1,12 +1,13 @@
 use std::borrow::Cow;
 use std::env;
 use std::net::{IpAddr, Ipv6Addr};
-use std::num::NonZeroU64;
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
 use arc_swap::ArcSwap;
+use axum::http::HeaderValue;
 use color_eyre::eyre::{self, bail, Context};
 use const_str::{concat, convert_ascii_case};
 use notify_debouncer_full::notify::RecursiveMode;
@@ -15,12 +16,13 @@ use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::select;
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info, instrument, trace};
+use tracing::{error, info, instrument, trace, warn};
 use url::Url;
 
 use crate::de::*;
+use crate::validate::validate;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(default)]
 pub struct SyntectConfig {
     pub load_defaults: bool,
@@ -38,10 +40,16 @@ pub struct CacheConfig {
     #[serde(deserialize_with = "check_millis")]
     pub cleanup_interval: Option<NonZeroU64>,
     pub persistence: bool,
+    #[serde(deserialize_with = "check_millis")]
+    pub save_interval: Option<NonZeroU64>,
     pub file: Box<Path>,
     pub compress: bool,
+    pub compression: Compression,
     #[serde(deserialize_with = "check_zstd_level_bounds")]
     pub compression_level: i32,
+    pub max_entries: Option<NonZeroUsize>,
+    pub max_bytes: Option<NonZeroUsize>,
+    pub warm_on_start: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -49,6 +57,51 @@ pub struct CacheConfig {
 pub struct HttpConfig {
     pub host: IpAddr,
     pub port: u16,
+    #[serde(deserialize_with = "check_unix_socket")]
+    pub unix_socket: Option<Box<Path>>,
+    pub http2: bool,
+    pub keep_alive: bool,
+    #[serde(deserialize_with = "check_millis")]
+    pub header_read_timeout: Option<NonZeroU64>,
+    #[serde(deserialize_with = "check_millis")]
+    pub keep_alive_interval: Option<NonZeroU64>,
+    #[serde(deserialize_with = "check_millis")]
+    pub keep_alive_timeout: Option<NonZeroU64>,
+    pub max_concurrent_streams: Option<u32>,
+    pub max_posts: usize,
+    #[serde(deserialize_with = "check_base_path")]
+    pub base_path: Box<str>,
+    pub compression: bool,
+    #[serde(deserialize_with = "check_origins")]
+    pub cors_allowed_origins: Vec<String>,
+    pub rate_limit: RateLimitConfig,
+    pub tls: Option<TlsConfig>,
+    pub cache_control: CacheControlConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TlsConfig {
+    pub cert: Box<Path>,
+    pub key: Box<Path>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enable: bool,
+    pub per_second: NonZeroU32,
+    pub burst: NonZeroU32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct CacheControlConfig {
+    pub index: Option<Box<str>>,
+    pub post: Option<Box<str>>,
+    pub feed: Option<Box<str>>,
+    #[serde(rename = "static")]
+    pub static_: Option<Box<str>>,
+    pub media: Option<Box<str>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -58,12 +111,65 @@ pub struct DirsConfig {
     #[serde(rename = "static")]
     pub static_: Box<Path>,
     pub templates: Box<Path>,
+    pub precompressed: Vec<Precompressed>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct RssConfig {
     pub enable: bool,
     pub link: Url,
+    pub language: Option<Box<str>>,
+    pub image: Option<Url>,
+    pub full_content: bool,
+    pub max_items: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub enable: bool,
+    pub url: Url,
+    pub method: Box<str>,
+    pub secret: Option<Box<str>>,
+    pub debounce: u64,
+    pub retries: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct PurgeConfig {
+    pub enable: bool,
+    pub endpoint: Url,
+    pub method: Box<str>,
+    pub auth: Option<Box<str>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enable: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct RobotsConfig {
+    #[serde(deserialize_with = "check_robots_paths")]
+    pub disallow: Vec<Box<str>>,
+    #[serde(deserialize_with = "check_robots_sitemap")]
+    pub sitemap: Option<Box<str>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub token: Option<Box<str>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct JsonConfig {
+    pub content_hash: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -93,6 +199,10 @@ pub struct StyleConfig {
     pub date_format: DateFormat,
     pub default_sort: Sort,
     pub default_color: Option<Box<str>>,
+    pub words_per_minute: u32,
+    pub auto_description_length: usize,
+    pub related_count: usize,
+    pub mermaid_url: Box<str>,
 }
 
 impl Default for StyleConfig {
@@ -105,6 +215,10 @@ impl Default for StyleConfig {
             date_format: Default::default(),
             default_sort: Default::default(),
             default_color: Default::default(),
+            words_per_minute: 200,
+            auto_description_length: 160,
+            related_count: 3,
+            mermaid_url: "https://cdn.jsdelivr.net/npm/mermaid@11/dist/mermaid.esm.min.mjs".into(),
         }
     }
 }
@@ -116,13 +230,37 @@ pub struct DisplayDates {
     pub modification: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct SsrConfig {
+    pub katex: Box<Path>,
+    pub mermaid: Box<Path>,
+    pub dot: Box<Path>,
+    pub timeout_ms: NonZeroU64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightMode {
+    #[default]
+    Inline,
+    Classes,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
 #[serde(default)]
 pub struct MarkdownRenderConfig {
     pub syntect: SyntectConfig,
+    pub highlight_mode: HighlightMode,
     pub escape: bool,
     #[serde(rename = "unsafe")]
     pub unsafe_: bool,
+    pub footnotes: bool,
+    pub math: bool,
+    pub math_ssr: bool,
+    pub diagram_ssr: bool,
+    pub mermaid: bool,
+    pub ssr: SsrConfig,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -131,6 +269,10 @@ pub struct MarkdownConfig {
     pub root: Box<Path>,
     pub render: MarkdownRenderConfig,
     pub raw_access: bool,
+    #[serde(deserialize_with = "check_content_type")]
+    pub raw_content_type: Box<str>,
+    pub more_marker: Box<str>,
+    pub concurrency: NonZeroUsize,
 }
 
 impl Default for MarkdownConfig {
@@ -139,6 +281,9 @@ impl Default for MarkdownConfig {
             root: PathBuf::from("posts").into(),
             render: Default::default(),
             raw_access: true,
+            raw_content_type: "text/markdown; charset=utf-8".into(),
+            more_marker: "<!--more-->".into(),
+            concurrency: NonZeroUsize::new(8).unwrap(),
         }
     }
 }
@@ -149,6 +294,28 @@ pub struct BlagConfig {
     pub root: Box<Path>,
     pub bin: Box<Path>,
     pub raw_access: bool,
+    pub max_concurrency: NonZeroUsize,
+    pub timeout_ms: NonZeroU64,
+    pub forward_headers: Vec<Box<str>>,
+}
+
+/// precompressed variants of static files to look for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Precompressed {
+    Br,
+    Gzip,
+    Zstd,
+    Deflate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    Zstd,
+    Gzip,
+    None,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
@@ -159,44 +326,62 @@ pub enum EngineMode {
     Blag,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlugConflict {
+    #[default]
+    FirstWins,
+    LastWins,
+    Error,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default, rename_all = "lowercase")]
 pub struct Engine {
     pub mode: EngineMode,
+    pub slug_conflict: SlugConflict,
     pub markdown: MarkdownConfig,
     pub blag: BlagConfig,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Config {
     pub engine: Engine,
     pub style: StyleConfig,
     pub rss: RssConfig,
+    pub json: JsonConfig,
+    pub webhook: WebhookConfig,
+    pub purge: PurgeConfig,
+    pub metrics: MetricsConfig,
+    pub admin: AdminConfig,
+    pub robots: RobotsConfig,
     #[serde(rename = "custom")]
     pub dirs: DirsConfig,
     pub http: HttpConfig,
     pub cache: CacheConfig,
 }
 
-impl Default for Config {
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            url: Url::parse("http://example.com").unwrap(),
+            method: "POST".into(),
+            secret: None,
+            debounce: 1000,
+            retries: 3,
+        }
+    }
+}
+
+impl Default for PurgeConfig {
     fn default() -> Self {
         Self {
-            engine: Default::default(),
-            style: Default::default(),
-            // i have a love-hate relationship with serde
-            // it was engimatic at first, but then i started actually using it
-            // writing my own serialize and deserialize implementations.. spending
-            // a lot of time in the docs trying to understand each and every option..
-            // now with this knowledge i can do stuff like this! (see rss field)
-            // and i'm proud to say that it still makes 0 sense.
-            rss: RssConfig {
-                enable: false,
-                link: Url::parse("http://example.com").unwrap(),
-            },
-            dirs: Default::default(),
-            http: Default::default(),
-            cache: Default::default(),
+            enable: false,
+            endpoint: Url::parse("http://example.com").unwrap(),
+            method: "POST".into(),
+            auth: None,
         }
     }
 }
@@ -216,6 +401,7 @@ impl Default for DirsConfig {
             media: PathBuf::from("media").into_boxed_path(),
             static_: PathBuf::from("static").into_boxed_path(),
             templates: PathBuf::from("templates").into_boxed_path(),
+            precompressed: vec![Precompressed::Br, Precompressed::Gzip],
         }
     }
 }
@@ -225,6 +411,43 @@ impl Default for HttpConfig {
         Self {
             host: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
             port: 3000,
+            unix_socket: None,
+            http2: false,
+            keep_alive: true,
+            header_read_timeout: None,
+            keep_alive_interval: None,
+            keep_alive_timeout: None,
+            max_concurrent_streams: None,
+            max_posts: 100,
+            base_path: "".into(),
+            compression: true,
+            cors_allowed_origins: Vec::new(),
+            rate_limit: Default::default(),
+            tls: None,
+            cache_control: Default::default(),
+        }
+    }
+}
+
+impl Default for RssConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            link: Url::parse("http://example.com").unwrap(),
+            language: None,
+            image: None,
+            full_content: true,
+            max_items: None,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            per_second: NonZeroU32::new(5).unwrap(),
+            burst: NonZeroU32::new(20).unwrap(),
         }
     }
 }
@@ -239,6 +462,17 @@ impl Default for SyntectConfig {
     }
 }
 
+impl Default for SsrConfig {
+    fn default() -> Self {
+        Self {
+            katex: PathBuf::from("katex").into(),
+            mermaid: PathBuf::from("mmdc").into(),
+            dot: PathBuf::from("dot").into(),
+            timeout_ms: NonZeroU64::new(5000).unwrap(),
+        }
+    }
+}
+
 impl Default for CacheConfig {
     fn default() -> Self {
         Self {
@@ -247,9 +481,14 @@ impl Default for CacheConfig {
             cleanup: true,
             cleanup_interval: None,
             persistence: true,
+            save_interval: None,
             file: PathBuf::from("cache").into(),
             compress: true,
+            compression: Default::default(),
             compression_level: 3,
+            max_entries: None,
+            max_bytes: None,
+            warm_on_start: false,
         }
     }
 }
@@ -260,27 +499,121 @@ impl Default for BlagConfig {
             root: PathBuf::from("posts").into(),
             bin: PathBuf::from("blag").into(),
             raw_access: true,
+            max_concurrency: std::thread::available_parallelism()
+                .unwrap_or(NonZeroUsize::new(1).unwrap()),
+            timeout_ms: NonZeroU64::new(5000).unwrap(),
+            forward_headers: Vec::new(),
         }
     }
 }
 
-fn config_path() -> Cow<'static, str> {
-    env::var(concat!(
-        convert_ascii_case!(shouty_snake, env!("CARGO_BIN_NAME")),
-        "_CONFIG"
-    ))
-    .map(Into::into)
-    .unwrap_or("config.toml".into())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// picks the format based on the file extension, defaulting to TOML
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::Json,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Toml,
+        }
+    }
+
+    fn parse(self, buf: &str) -> eyre::Result<Config> {
+        match self {
+            Self::Toml => toml::from_str(buf).context("couldn't parse TOML configuration"),
+            Self::Json => serde_json::from_str(buf).context("couldn't parse JSON configuration"),
+            Self::Yaml => serde_yaml::from_str(buf).context("couldn't parse YAML configuration"),
+        }
+    }
+
+    fn serialize(self, config: &Config) -> eyre::Result<String> {
+        match self {
+            Self::Toml => {
+                toml::to_string_pretty(config).context("couldn't serialize TOML configuration")
+            }
+            Self::Json => serde_json::to_string_pretty(config)
+                .context("couldn't serialize JSON configuration"),
+            Self::Yaml => {
+                serde_yaml::to_string(config).context("couldn't serialize YAML configuration")
+            }
+        }
+    }
+}
+
+const ENV_PREFIX: &str = concat!(
+    convert_ascii_case!(shouty_snake, env!("CARGO_BIN_NAME")),
+    "_"
+);
+const CONFIG_VAR: &str = concat!(ENV_PREFIX, "CONFIG");
+
+pub(crate) fn config_path() -> Cow<'static, str> {
+    env::var(CONFIG_VAR)
+        .map(Into::into)
+        .unwrap_or("config.toml".into())
+}
+
+/// parses an override, going by the type of the value it replaces. strings
+/// are taken as-is, everything else (and unset options) is parsed as JSON
+fn override_value(
+    var: &str,
+    raw: String,
+    current: &serde_json::Value,
+) -> eyre::Result<serde_json::Value> {
+    use serde_json::Value;
+
+    match current {
+        Value::String(_) => Ok(Value::String(raw)),
+        Value::Null => Ok(serde_json::from_str(&raw).unwrap_or(Value::String(raw))),
+        _ => serde_json::from_str(&raw).with_context(|| format!("{var} isn't valid JSON")),
+    }
+}
+
+/// applies `BINGUS_BLOG_SECTION__FIELD=value` environment variables on top of
+/// the loaded config, with `__` between nested keys
+fn apply_env_overrides(config: Config) -> eyre::Result<Config> {
+    let mut overrides: Vec<(String, String)> = env::vars()
+        .filter(|(var, _)| var.starts_with(ENV_PREFIX) && var != CONFIG_VAR)
+        .collect();
+    if overrides.is_empty() {
+        return Ok(config);
+    }
+    overrides.sort_unstable();
+
+    let mut value = serde_json::to_value(&config).context("couldn't serialize configuration")?;
+    for (var, raw) in overrides {
+        let keys: Vec<String> = var[ENV_PREFIX.len()..]
+            .split("__")
+            .map(str::to_ascii_lowercase)
+            .collect();
+        let Some(current) = keys
+            .iter()
+            .try_fold(&mut value, |value, key| value.get_mut(key))
+        else {
+            trace!("ignoring {var}, it doesn't name a config option");
+            continue;
+        };
+        *current = override_value(&var, raw, current)?;
+        info!("overriding {} from {var}", keys.join("."));
+    }
+
+    serde_json::from_value(value).context("couldn't apply configuration overrides")
 }
 
 pub async fn load_from(path: (impl AsRef<Path> + std::fmt::Debug)) -> eyre::Result<Config> {
+    let format = ConfigFormat::from_path(path.as_ref());
     match tokio::fs::OpenOptions::new().read(true).open(&path).await {
         Ok(mut file) => {
             let mut buf = String::new();
             file.read_to_string(&mut buf)
                 .await
                 .context("couldn't read configuration file")?;
-            toml::from_str(&buf).context("couldn't parse configuration")
+            format.parse(&buf)
         }
         Err(err) => match err.kind() {
             std::io::ErrorKind::NotFound => {
@@ -294,11 +627,7 @@ pub async fn load_from(path: (impl AsRef<Path> + std::fmt::Debug)) -> eyre::Resu
                     .await
                 {
                     Ok(mut file) => file
-                        .write_all(
-                            toml::to_string_pretty(&config)
-                                .context("couldn't serialize configuration")?
-                                .as_bytes(),
-                        )
+                        .write_all(format.serialize(&config)?.as_bytes())
                         .await
                         .unwrap_or_else(|err| error!("couldn't write configuration: {err}")),
                     Err(err) => error!("couldn't open file {path:?} for writing: {err}"),
@@ -313,28 +642,44 @@ pub async fn load_from(path: (impl AsRef<Path> + std::fmt::Debug)) -> eyre::Resu
 #[instrument]
 pub async fn load() -> eyre::Result<(Config, Cow<'static, str>)> {
     let config_file = config_path();
-    let config = load_from(&*config_file).await?;
+    let config = apply_env_overrides(load_from(&*config_file).await?)?;
     Ok((config, config_file))
 }
 
+/// what happened to the config after an event
+enum Reload {
+    Ignored,
+    Applied,
+    /// the new config failed validation, so the old one is still in use
+    Kept(eyre::Report),
+}
+
 async fn process_event(
     event: DebouncedEvent,
     config_file: &Path,
     swapper: &ArcSwap<Config>,
-) -> eyre::Result<()> {
+) -> eyre::Result<Reload> {
     if !event.kind.is_modify() && !event.kind.is_create()
         || !event.paths.iter().any(|p| p == config_file)
     {
         trace!("not interested: {event:?}");
-        return Ok(());
+        return Ok(Reload::Ignored);
     }
 
-    let config = load_from(config_file).await?;
-    info!("reloaded config from {config_file:?}");
+    let config = apply_env_overrides(load_from(config_file).await?)?;
+    // the checks touch the filesystem
+    let (config, validation) = tokio::task::spawn_blocking(move || {
+        let validation = validate(&config);
+        (config, validation)
+    })
+    .await?;
+    if let Err(err) = validation {
+        return Ok(Reload::Kept(err));
+    }
 
     swapper.store(Arc::new(config));
 
-    Ok(())
+    Ok(Reload::Applied)
 }
 
 #[instrument(skip_all)]
@@ -378,8 +723,11 @@ pub async fn watcher(
         };
 
         for event in events {
-            if let Err(err) = process_event(event, &config_file, &swapper).await {
-                error!("error while processing event: {err}");
+            match process_event(event, &config_file, &swapper).await {
+                Ok(Reload::Ignored) => {}
+                Ok(Reload::Applied) => info!("reloaded config from {config_file:?}"),
+                Ok(Reload::Kept(err)) => warn!("kept previous config due to: {err:#}"),
+                Err(err) => error!("error while processing event: {err}"),
             }
         }
     }
@@ -395,6 +743,99 @@ where
         .map(|x| x as i32)
 }
 
+fn check_base_path<'de, D>(d: D) -> Result<Box<str>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let path = String::deserialize(d)?;
+    parse_base_path(&path).map_err(serde::de::Error::custom)
+}
+
+/// strips trailing slashes off a base path and makes sure it's root-relative
+pub fn parse_base_path(path: &str) -> Result<Box<str>, &'static str> {
+    let path = path.trim_end_matches('/');
+    if !path.is_empty() && !path.starts_with('/') {
+        return Err("base path must start with a slash");
+    }
+    Ok(path.into())
+}
+
+/// robots.txt is line based, so paths must be a single root-relative token
+fn check_robots_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') || path.contains(char::is_whitespace) {
+        return Err(format!(
+            "invalid path {path:?}, expected a root-relative path without whitespace"
+        ));
+    }
+    Ok(())
+}
+
+fn check_robots_paths<'de, D>(d: D) -> Result<Vec<Box<str>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let paths = Vec::<Box<str>>::deserialize(d)?;
+    for path in &paths {
+        check_robots_path(path).map_err(serde::de::Error::custom)?;
+    }
+    Ok(paths)
+}
+
+fn check_robots_sitemap<'de, D>(d: D) -> Result<Option<Box<str>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let path = Option::<Box<str>>::deserialize(d)?;
+    if let Some(path) = &path {
+        check_robots_path(path).map_err(serde::de::Error::custom)?;
+    }
+    Ok(path)
+}
+
+fn check_content_type<'de, D>(d: D) -> Result<Box<str>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let content_type = Box::<str>::deserialize(d)?;
+    if HeaderValue::from_str(&content_type).is_err() {
+        return Err(serde::de::Error::custom(format!(
+            "{content_type:?} isn't a valid header value"
+        )));
+    }
+    Ok(content_type)
+}
+
+fn check_unix_socket<'de, D>(d: D) -> Result<Option<Box<Path>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let path = Option::<Box<Path>>::deserialize(d)?;
+    if cfg!(not(unix)) && path.is_some() {
+        return Err(serde::de::Error::custom(
+            "unix sockets are only supported on unix",
+        ));
+    }
+    Ok(path)
+}
+
+fn check_origins<'de, D>(d: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let origins = Vec::<String>::deserialize(d)?;
+    for origin in &origins {
+        // an origin is exactly what a url serializes its origin to
+        let valid = origin == "*"
+            || Url::parse(origin).is_ok_and(|url| url.origin().ascii_serialization() == *origin);
+        if !valid {
+            return Err(serde::de::Error::custom(format!(
+                "invalid origin {origin:?}, expected something like \"https://example.com\""
+            )));
+        }
+    }
+    Ok(origins)
+}
+
 fn check_millis<'de, D>(d: D) -> Result<Option<NonZeroU64>, D::Error>
 where
     D: serde::Deserializer<'de>,
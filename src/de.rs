@@ -41,6 +41,15 @@ impl<const START: i64, const END: i64> serde::de::Visitor<'_> for RangedI64Visit
     {
         self.visit_i64(v as i64)
     }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        i64::try_from(v)
+            .map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))
+            .and_then(|v| self.visit_i64(v))
+    }
 }
 
 pub struct U64Visitor;
@@ -83,4 +92,11 @@ impl<'de> Visitor<'de> for MillisVisitor {
             .ok_or(D::Error::invalid_value(Unexpected::Unsigned(n), &self))
             .map(Some)
     }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(None)
+    }
 }
@@ -18,6 +18,8 @@ pub enum PostError {
     RenderError(String),
     #[error("post {0:?} not found")]
     NotFound(Arc<str>),
+    #[error("slug {0:?} is used by both {1} and {2}")]
+    SlugConflict(Arc<str>, String, String),
     #[error("unexpected: {0}")]
     Other(#[from] eyre::Error),
 }
@@ -47,7 +49,11 @@ impl From<serde_json::Error> for PostError {
 
 impl IntoResponse for PostError {
     fn into_response(self) -> Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+        let status_code = match &self {
+            PostError::NotFound(_) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status_code, self.to_string()).into_response()
     }
 }
 
@@ -61,6 +67,17 @@ pub enum AppError {
     HandlebarsError(#[from] handlebars::RenderError),
     #[error("rss is disabled")]
     RssDisabled,
+    #[error("metrics are disabled")]
+    MetricsDisabled,
+    // admin routes are not advertised when disabled
+    #[error("not found")]
+    AdminDisabled,
+    #[error("page not found")]
+    NotFound,
+    #[error("unauthorized")]
+    Unauthorized,
+    #[error("too many requests")]
+    TooManyRequests,
     #[error(transparent)]
     UrlError(#[from] url::ParseError),
 }
@@ -72,6 +89,10 @@ impl From<std::io::Error> for AppError {
     }
 }
 
+/// the message of an error response, for the app to render it differently
+#[derive(Clone)]
+pub struct ErrorMessage(pub String);
+
 #[derive(Template)]
 #[template(path = "error.html")]
 struct ErrorTemplate {
@@ -85,12 +106,23 @@ impl IntoResponse for AppError {
 
         let status_code = match &self {
             AppError::PostError(PostError::NotFound(_)) => StatusCode::NOT_FOUND,
-            AppError::RssDisabled => StatusCode::FORBIDDEN,
+            AppError::RssDisabled | AppError::MetricsDisabled => StatusCode::FORBIDDEN,
+            AppError::AdminDisabled | AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
+        // the app doesn't see the error itself, and may render it as JSON or
+        // with the `404` template instead
+        let message = ErrorMessage(error.clone());
+
         match (ErrorTemplate { error }.render()) {
-            Ok(rendered) => (status_code, Html(rendered)).into_response(),
+            Ok(rendered) => {
+                let mut response = (status_code, Html(rendered)).into_response();
+                response.extensions_mut().insert(message);
+                response
+            }
             Err(err) => {
                 error!("error while rendering error template: {err}");
                 (
@@ -102,3 +134,74 @@ impl IntoResponse for AppError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use handlebars::RenderErrorReason;
+
+    use super::*;
+
+    fn post_errors() -> [(PostError, StatusCode); 6] {
+        [
+            (
+                std::io::Error::other("bingus").into(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                PostError::ParseError("bingus".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                PostError::RenderError("bingus".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (PostError::NotFound("bingus".into()), StatusCode::NOT_FOUND),
+            (
+                PostError::SlugConflict("bingus".into(), "a.md".into(), "b.md".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                eyre::eyre!("bingus").into(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ]
+    }
+
+    #[test]
+    fn post_error_status() {
+        for (err, status) in post_errors() {
+            let name = format!("{err:?}");
+            assert_eq!(err.into_response().status(), status, "{name}");
+        }
+    }
+
+    #[test]
+    fn app_error_status() {
+        let errors = post_errors()
+            .map(|(err, status)| (AppError::PostError(err), status))
+            .into_iter()
+            .chain([
+                (
+                    AppError::HandlebarsError(RenderErrorReason::Other("bingus".into()).into()),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+                (AppError::RssDisabled, StatusCode::FORBIDDEN),
+                (AppError::MetricsDisabled, StatusCode::FORBIDDEN),
+                (AppError::AdminDisabled, StatusCode::NOT_FOUND),
+                (AppError::NotFound, StatusCode::NOT_FOUND),
+                (AppError::Unauthorized, StatusCode::UNAUTHORIZED),
+                (AppError::TooManyRequests, StatusCode::TOO_MANY_REQUESTS),
+                (
+                    url::ParseError::EmptyHost.into(),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            ]);
+
+        for (err, status) in errors {
+            let name = format!("{err:?}");
+            let response = err.into_response();
+            assert_eq!(response.status(), status, "{name}");
+            assert!(response.extensions().get::<ErrorMessage>().is_some());
+        }
+    }
+}
@@ -1,10 +1,17 @@
+use std::borrow::Cow;
 use std::fmt::Display;
 use std::time::Duration;
 
 use chrono::{DateTime, TimeZone, Utc};
-use handlebars::handlebars_helper;
+use handlebars::{
+    handlebars_helper, html_escape, Context, Handlebars, Helper, HelperDef, HelperResult, Output,
+    RenderContext,
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
 use crate::config::DateFormat;
+use crate::path::{with_base_path, BasePathAccess};
+use crate::text;
 
 fn date_impl<T>(date_time: &DateTime<T>, date_format: &DateFormat) -> String
 where
@@ -22,3 +29,164 @@ handlebars_helper!(date: |date_time: Option<DateTime<Utc>>, date_format: DateFor
 });
 
 handlebars_helper!(duration: |duration_: Duration| format!("{:?}", duration_));
+
+/// `3 days ago`, or `in 2 hours` for dates in the future
+fn time_ago(date_time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let delta = now.signed_duration_since(date_time);
+    let secs = delta.num_seconds().unsigned_abs();
+    let (n, unit) = match secs {
+        0 => return "just now".into(),
+        1..60 => (secs, "second"),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => match secs / 86400 {
+            days @ 0..30 => (days, "day"),
+            days @ 30..365 => (days / 30, "month"),
+            days => (days / 365, "year"),
+        },
+    };
+    let plural = if n == 1 { "" } else { "s" };
+
+    if delta.num_seconds() < 0 {
+        format!("in {n} {unit}{plural}")
+    } else {
+        format!("{n} {unit}{plural} ago")
+    }
+}
+
+handlebars_helper!(timeago: |date_time: DateTime<Utc>| time_ago(date_time, Utc::now()));
+
+handlebars_helper!(slugify: |text: str| text::slugify(text));
+
+handlebars_helper!(truncate: |text: str, max: u64| text::truncate_words(text, max as usize));
+
+/// joins its parameters into a url, prefixed with the base path if it's
+/// root-relative. every parameter after the first is percent-encoded as a
+/// single path segment, so `{{url "/tags/" tag}}` works for any tag
+pub fn url(base_path: BasePathAccess) -> impl HelperDef + Send + Sync {
+    move |h: &Helper,
+          _: &Handlebars,
+          _: &Context,
+          _: &mut RenderContext,
+          out: &mut dyn Output|
+          -> HelperResult {
+        let mut url = String::new();
+        for (i, param) in h.params().iter().enumerate() {
+            let value = match param.value() {
+                serde_json::Value::String(s) => Cow::Borrowed(s.as_str()),
+                value => Cow::Owned(value.to_string()),
+            };
+            if i == 0 {
+                url += &value;
+            } else {
+                url.extend(utf8_percent_encode(&value, PATH_SEGMENT));
+            }
+        }
+
+        out.write(&html_escape(&with_base_path(&base_path.load(), &url)))?;
+        Ok(())
+    }
+}
+
+/// everything but unreserved characters, for a single path segment
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// links each tag in a list (or the keys of a tag-to-count map) to its tag
+/// page, separated by spaces
+pub fn tag_links(base_path: BasePathAccess) -> impl HelperDef + Send + Sync {
+    move |h: &Helper,
+          _: &Handlebars,
+          _: &Context,
+          _: &mut RenderContext,
+          out: &mut dyn Output|
+          -> HelperResult {
+        let tags: Vec<&str> = match h.param(0).map(|param| param.value()) {
+            Some(serde_json::Value::Array(tags)) => {
+                tags.iter().filter_map(|tag| tag.as_str()).collect()
+            }
+            Some(serde_json::Value::Object(tags)) => tags.keys().map(String::as_str).collect(),
+            _ => Vec::new(),
+        };
+
+        let base_path = base_path.load();
+        for (i, tag) in tags.into_iter().enumerate() {
+            if i != 0 {
+                out.write(" ")?;
+            }
+            let url = format!("/tags/{}", utf8_percent_encode(tag, PATH_SEGMENT));
+            out.write(&format!(
+                "<a href=\"{}\" class=\"tag\" title=\"view all posts with this tag\">{}</a>",
+                html_escape(&with_base_path(&base_path, &url)),
+                html_escape(tag),
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn slugify_template(title: &str) -> String {
+        let mut reg = Handlebars::new();
+        reg.register_helper("slugify", Box::new(slugify));
+        reg.render_template("{{slugify title}}", &json!({ "title": title }))
+            .unwrap()
+    }
+
+    #[test]
+    fn slugify_spaces() {
+        assert_eq!(slugify_template("Hello World"), "hello-world");
+        assert_eq!(
+            slugify_template("  padded\tand\n spaced  "),
+            "padded-and-spaced"
+        );
+    }
+
+    #[test]
+    fn slugify_punctuation() {
+        assert_eq!(slugify_template("Hello, World!!"), "hello-world");
+        assert_eq!(
+            slugify_template("rust/async: a <guide>"),
+            "rust-async-a-guide"
+        );
+        assert_eq!(slugify_template("C++ & C#"), "c-c");
+        assert_eq!(slugify_template("---"), "");
+    }
+
+    #[test]
+    fn slugify_accents() {
+        assert_eq!(slugify_template("Café Crème"), "cafe-creme");
+        assert_eq!(slugify_template("Ñandú über"), "nandu-uber");
+        // decomposed, with a combining acute accent
+        assert_eq!(slugify_template("Cafe\u{301}"), "cafe");
+    }
+
+    #[test]
+    fn url_encodes_segments() {
+        let base_path: BasePathAccess =
+            Box::new(arc_swap::ArcSwap::from_pointee(Box::<str>::from("/blog")));
+        let mut reg = Handlebars::new();
+        reg.register_helper("url", Box::new(url(base_path)));
+        let render = |template: &str| {
+            reg.render_template(template, &json!({ "tag": "a b#c", "name": "2024/hello" }))
+                .unwrap()
+        };
+        assert_eq!(render(r#"{{url "/tags/" tag}}"#), "/blog/tags/a%20b%23c");
+        assert_eq!(
+            render(r#"{{url "/posts/" name}}"#),
+            "/blog/posts/2024%2Fhello"
+        );
+        assert_eq!(
+            render(r#"{{url "https://example.com/" tag}}"#),
+            "https://example.com/a%20b%23c"
+        );
+    }
+}
@@ -1,6 +1,8 @@
 #![feature(let_chains, pattern, path_add_extension, if_let_guard)]
 
 mod app;
+mod cli;
+mod color;
 mod config;
 mod de;
 mod error;
@@ -9,11 +11,16 @@ mod markdown_render;
 mod path;
 mod platform;
 mod post;
+mod purge;
+mod rate_limit;
+mod serve;
 mod serve_dir_included;
 mod systemtime_as_secs;
 mod templates;
+mod text;
+mod validate;
+mod webhook;
 
-use std::future::IntoFuture;
 use std::net::SocketAddr;
 use std::process::exit;
 use std::sync::Arc;
@@ -23,6 +30,10 @@ use arc_swap::access::Map;
 use arc_swap::ArcSwap;
 use color_eyre::eyre::{self, Context};
 use config::{Config, EngineMode};
+use futures::FutureExt;
+use handlebars::Handlebars;
+use indexmap::IndexMap;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
 use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 use tokio::task::JoinSet;
@@ -35,14 +46,103 @@ use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::{util::SubscriberInitExt, EnvFilter};
 
 use crate::app::AppState;
+use crate::path::BasePathAccess;
 use crate::post::cache::{load_cache, Cache, CacheGuard, CACHE_VERSION};
-use crate::post::{Blag, MarkdownPosts, PostManager};
+use crate::post::slugs::SlugConflictAccess;
+use crate::post::{Blag, MarkdownPosts, PostManager, StyleAccess};
 use crate::templates::new_registry;
 use crate::templates::watcher::watch_templates;
 
+fn new_templates(
+    config: &Config,
+    swapper: &Arc<ArcSwap<Config>>,
+) -> eyre::Result<Handlebars<'static>> {
+    let start = Instant::now();
+    // NOTE: use tokio::task::spawn_blocking if this ever turns into a concurrent task
+    let mut reg = new_registry(&config.dirs.templates).with_context(|| {
+        format!(
+            "failed to load custom templates from {:?}",
+            config.dirs.templates
+        )
+    })?;
+    reg.register_helper("date", Box::new(helpers::date));
+    reg.register_helper("duration", Box::new(helpers::duration));
+    reg.register_helper("slugify", Box::new(helpers::slugify));
+    reg.register_helper("truncate", Box::new(helpers::truncate));
+    reg.register_helper("timeago", Box::new(helpers::timeago));
+    reg.register_helper(
+        "tag_links",
+        Box::new(helpers::tag_links(Box::new(Map::new(
+            swapper.clone(),
+            |c: &Config| &c.http.base_path,
+        )))),
+    );
+    reg.register_helper(
+        "url",
+        Box::new(helpers::url(Box::new(Map::new(
+            swapper.clone(),
+            |c: &Config| &c.http.base_path,
+        )))),
+    );
+    debug!(duration = ?start.elapsed(), "registered all templates");
+    Ok(reg)
+}
+
+async fn new_posts(
+    config: &Config,
+    swapper: &Arc<ArcSwap<Config>>,
+    cache: Option<Arc<CacheGuard>>,
+) -> eyre::Result<Arc<dyn PostManager + Send + Sync>> {
+    let slug_conflict: SlugConflictAccess = Box::new(Map::new(swapper.clone(), |c: &Config| {
+        &c.engine.slug_conflict
+    }));
+    let style: StyleAccess = Box::new(Map::new(swapper.clone(), |c: &Config| &c.style));
+    let base_path: BasePathAccess =
+        Box::new(Map::new(swapper.clone(), |c: &Config| &c.http.base_path));
+
+    Ok(match config.engine.mode {
+        EngineMode::Markdown => {
+            let access = Map::new(swapper.clone(), |c: &Config| &c.engine.markdown);
+            Arc::new(MarkdownPosts::new(access, cache, slug_conflict, style, base_path).await?)
+        }
+        EngineMode::Blag => {
+            let access = Map::new(swapper.clone(), |c: &Config| &c.engine.blag);
+            Arc::new(Blag::new(access, cache, slug_conflict, style, base_path))
+        }
+    })
+}
+
+fn new_state(
+    swapper: &Arc<ArcSwap<Config>>,
+    posts: Arc<dyn PostManager + Send + Sync>,
+    templates: Arc<RwLock<Handlebars<'static>>>,
+    metrics: Option<PrometheusHandle>,
+) -> AppState {
+    AppState {
+        rss: Arc::new(Map::new(swapper.clone(), |c: &Config| &c.rss)),
+        json: Arc::new(Map::new(swapper.clone(), |c: &Config| &c.json)),
+        cache_control: Arc::new(Map::new(swapper.clone(), |c: &Config| {
+            &c.http.cache_control
+        })),
+        style: Arc::new(Map::new(swapper.clone(), |c: &Config| &c.style)),
+        max_posts: Arc::new(Map::new(swapper.clone(), |c: &Config| &c.http.max_posts)),
+        base_path: Arc::new(Map::new(swapper.clone(), |c: &Config| &c.http.base_path)),
+        admin: Arc::new(Map::new(swapper.clone(), |c: &Config| &c.admin)),
+        robots: Arc::new(Map::new(swapper.clone(), |c: &Config| &c.robots)),
+        posts,
+        listed_posts: Default::default(),
+        templates,
+        metrics,
+    }
+}
+
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     color_eyre::install()?;
+    if let Some(result) = cli::run().await {
+        return result;
+    }
+
     let reg = tracing_subscriber::registry();
     #[cfg(feature = "tokio-console")]
     let reg = reg.with(console_subscriber::spawn());
@@ -69,15 +169,30 @@ async fn main() -> eyre::Result<()> {
 
     info!("loaded config from {config_file:?}");
 
-    let start = Instant::now();
-    // NOTE: use tokio::task::spawn_blocking if this ever turns into a concurrent task
-    let mut reg =
-        new_registry(&config.dirs.templates).context("failed to create handlebars registry")?;
-    reg.register_helper("date", Box::new(helpers::date));
-    reg.register_helper("duration", Box::new(helpers::duration));
-    debug!(duration = ?start.elapsed(), "registered all templates");
+    let metrics = if config.metrics.enable {
+        let handle = PrometheusBuilder::new()
+            .install_recorder()
+            .context("failed to install metrics recorder")?;
 
-    let registry = Arc::new(RwLock::new(reg));
+        let upkeep = handle.clone();
+        let token = cancellation_token.child_token();
+        debug!("setting up metrics upkeep task");
+        tasks.spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(5));
+            loop {
+                select! {
+                    _ = token.cancelled() => break Ok(()),
+                    _ = interval.tick() => upkeep.run_upkeep(),
+                }
+            }
+        });
+
+        Some(handle)
+    } else {
+        None
+    };
+
+    let registry = Arc::new(RwLock::new(new_templates(&config, &swapper)?));
 
     debug!("setting up watcher");
     let watcher_token = cancellation_token.child_token();
@@ -93,17 +208,17 @@ async fn main() -> eyre::Result<()> {
             let mut cache = load_cache(&config.cache).await.unwrap_or_else(|err| {
                 error!("failed to load cache: {}", err);
                 info!("using empty cache");
-                Cache::new(config.cache.ttl)
+                Cache::new(&config.cache)
             });
 
             if cache.version() < CACHE_VERSION {
                 warn!("cache version changed, clearing cache");
-                cache = Cache::new(config.cache.ttl);
+                cache = Cache::new(&config.cache);
             };
 
             Some(cache)
         } else {
-            Some(Cache::new(config.cache.ttl))
+            Some(Cache::new(&config.cache))
         }
     } else {
         None
@@ -111,16 +226,7 @@ async fn main() -> eyre::Result<()> {
     .map(|cache| CacheGuard::new(cache, config_cache_access))
     .map(Arc::new);
 
-    let posts: Arc<dyn PostManager + Send + Sync> = match config.engine.mode {
-        EngineMode::Markdown => {
-            let access = Map::new(swapper.clone(), |c: &Config| &c.engine.markdown);
-            Arc::new(MarkdownPosts::new(access, cache.clone()).await?)
-        }
-        EngineMode::Blag => {
-            let access = Map::new(swapper.clone(), |c: &Config| &c.engine.blag);
-            Arc::new(Blag::new(access, cache.clone()))
-        }
-    };
+    let posts = new_posts(&config, &swapper, cache.clone()).await?;
 
     debug!("setting up config watcher");
 
@@ -128,6 +234,22 @@ async fn main() -> eyre::Result<()> {
 
     tasks.spawn(config::watcher(config_file, token, swapper.clone()));
 
+    if config.webhook.enable || config.purge.enable {
+        debug!("setting up post change watcher");
+        // only the markdown engine serves posts from subdirectories
+        let (root, ext, recursive) = match config.engine.mode {
+            EngineMode::Markdown => (config.engine.markdown.root.clone(), "md", true),
+            EngineMode::Blag => (config.engine.blag.root.clone(), "sh", false),
+        };
+        tasks.spawn(webhook::watcher(
+            root,
+            ext,
+            recursive,
+            cancellation_token.child_token(),
+            swapper.clone(),
+        ));
+    }
+
     if config.cache.enable && config.cache.cleanup {
         if let Some(millis) = config.cache.cleanup_interval {
             let posts = Arc::clone(&posts);
@@ -149,38 +271,100 @@ async fn main() -> eyre::Result<()> {
         }
     }
 
-    let state = AppState {
-        rss: Arc::new(Map::new(swapper.clone(), |c: &Config| &c.rss)),
-        style: Arc::new(Map::new(swapper.clone(), |c: &Config| &c.style)),
-        posts,
-        templates: registry,
-    };
-    let app = app::new(&config.dirs).with_state(state.clone());
+    if config.cache.persistence
+        && let Some(millis) = config.cache.save_interval
+        && let Some(cache) = &cache
+    {
+        let cache = Arc::clone(cache);
+        let token = cancellation_token.child_token();
+        debug!("setting up cache snapshot task");
+        tasks.spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(millis.into()));
+            // the first tick is immediate, and there's nothing new to save yet
+            interval.tick().await;
+            loop {
+                select! {
+                    _ = token.cancelled() => break Ok(()),
+                    _ = interval.tick() => {
+                        if let Err(err) = Arc::clone(&cache).snapshot().await {
+                            error!("failed to save cache snapshot: {err:?}");
+                        }
+                    }
+                }
+            }
+        });
+    }
 
-    let socket_addr = SocketAddr::new(config.http.host, config.http.port);
-    let listener = TcpListener::bind(socket_addr)
-        .await
-        .with_context(|| format!("couldn't listen on {}", socket_addr))?;
-    let local_addr = listener
-        .local_addr()
-        .context("couldn't get socket address")?;
-    info!("listening on http://{}", local_addr);
+    if config.cache.enable && config.cache.warm_on_start {
+        let posts = Arc::clone(&posts);
+        let token = cancellation_token.child_token();
+        debug!("setting up cache warm-up task");
+        tasks.spawn(async move {
+            info!("warming up cache");
+            let query = IndexMap::new();
+            let start = Instant::now();
+            select! {
+                _ = token.cancelled() => {},
+                // failing posts are logged and skipped by the engine
+                result = posts.get_all_posts(&[], &query) => match result {
+                    Ok(posts) => info!(
+                        duration = ?start.elapsed(),
+                        "warmed up cache with {} posts",
+                        posts.len()
+                    ),
+                    Err(err) => error!("failed to warm up cache: {err}"),
+                },
+            }
+            Ok(())
+        });
+    }
 
-    let sigint = signal::ctrl_c();
-    let sigterm = platform::sigterm();
+    let state = new_state(&swapper, posts, registry, metrics);
+    let app = app::new(&config, state.clone());
 
+    let tls = config
+        .http
+        .tls
+        .as_ref()
+        .map(|tls| serve::tls_acceptor(&config.http, tls))
+        .transpose()
+        .context("couldn't set up TLS")?;
+
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let builder = serve::builder(&config.http);
     let axum_token = cancellation_token.child_token();
 
-    let mut server = axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .with_graceful_shutdown(async move { axum_token.cancelled().await })
-    .into_future();
+    let mut server = match config.http.unix_socket.as_deref() {
+        #[cfg(unix)]
+        Some(path) => {
+            let listener = serve::bind_unix(path).await?;
+            info!("listening on {scheme} over unix socket {path:?}");
+            if config.http.rate_limit.enable {
+                // unix peers have no ip to be limited by
+                warn!("http.rate_limit has no effect on a unix socket");
+            }
+            serve::serve::<_, serve::UnixPeer>(listener, tls, app, builder, axum_token).boxed()
+        }
+        // rejected when the config is loaded on other platforms
+        _ => {
+            let socket_addr = SocketAddr::new(config.http.host, config.http.port);
+            let listener = TcpListener::bind(socket_addr)
+                .await
+                .with_context(|| format!("couldn't listen on {}", socket_addr))?;
+            let local_addr = listener
+                .local_addr()
+                .context("couldn't get socket address")?;
+            info!("listening on {scheme}://{local_addr}");
+            serve::serve::<_, SocketAddr>(listener, tls, app, builder, axum_token).boxed()
+        }
+    };
+
+    let sigint = signal::ctrl_c();
+    let sigterm = platform::sigterm();
 
     tokio::select! {
-        result = &mut server => {
-            result.context("failed to serve app")?;
+        _ = &mut server => {
+            warn!("server exited unexpectedly");
         },
         _ = sigint => {
             info!("received SIGINT, exiting gracefully");
@@ -190,10 +374,16 @@ async fn main() -> eyre::Result<()> {
         }
     };
 
+    let unix_socket = config.http.unix_socket.clone();
     let cleanup = async move {
         // stop tasks
         cancellation_token.cancel();
-        server.await.context("failed to serve app")?;
+        server.await;
+        if let Some(path) = unix_socket {
+            if let Err(err) = tokio::fs::remove_file(&path).await {
+                error!("failed to remove socket {path:?}: {err}");
+            }
+        }
         while let Some(task) = tasks.join_next().await {
             let res = task.context("failed to join task")?;
             if let Err(err) = res {
@@ -1,14 +1,26 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
 use color_eyre::eyre::{self, Context};
 use comrak::adapters::SyntaxHighlighterAdapter;
+use comrak::html::{escape, write_opening_tag};
+use comrak::nodes::{AstNode, NodeValue};
 use comrak::plugins::syntect::{SyntectAdapter, SyntectAdapterBuilder};
 use comrak::ComrakOptions;
 use comrak::RenderPlugins;
-use comrak::{markdown_to_html_with_plugins, Plugins};
+use comrak::{format_html_with_plugins, parse_document, Arena, Plugins};
 use syntect::highlighting::ThemeSet;
+use tracing::{debug, warn};
 
-use crate::config::MarkdownRenderConfig;
+use crate::config::{HighlightMode, MarkdownRenderConfig};
+use crate::path::with_base_path;
 
-pub fn build_syntect(config: &MarkdownRenderConfig) -> eyre::Result<SyntectAdapter> {
+pub fn load_themes(config: &MarkdownRenderConfig) -> eyre::Result<ThemeSet> {
     let mut theme_set = if config.syntect.load_defaults {
         ThemeSet::load_defaults()
     } else {
@@ -19,17 +31,340 @@ pub fn build_syntect(config: &MarkdownRenderConfig) -> eyre::Result<SyntectAdapt
             .add_from_folder(path)
             .with_context(|| format!("failed to add themes from {path:?}"))?;
     }
-    let mut builder = SyntectAdapterBuilder::new().theme_set(theme_set);
-    if let Some(theme) = config.syntect.theme.as_ref() {
-        builder = builder.theme(theme);
+    Ok(theme_set)
+}
+
+/// `ThemeSet` isn't `Clone`, but its themes are
+fn copy_themes(themes: &ThemeSet) -> ThemeSet {
+    ThemeSet {
+        themes: themes.themes.clone(),
+    }
+}
+
+pub fn build_syntect(themes: ThemeSet, theme: Option<&str>, mode: HighlightMode) -> SyntectAdapter {
+    let mut builder = SyntectAdapterBuilder::new().theme_set(themes);
+    match mode {
+        HighlightMode::Inline => {
+            if let Some(theme) = theme {
+                builder = builder.theme(theme);
+            }
+        }
+        HighlightMode::Classes => builder = builder.css(),
+    }
+    builder.build()
+}
+
+/// syntax highlighters for the configured theme and the ones posts ask for,
+/// built the first time they're needed
+pub struct Highlighters {
+    themes: ThemeSet,
+    mode: HighlightMode,
+    default: Arc<SyntectAdapter>,
+    by_theme: RwLock<HashMap<Arc<str>, Arc<SyntectAdapter>>>,
+}
+
+impl Highlighters {
+    pub fn new(config: &MarkdownRenderConfig) -> eyre::Result<Self> {
+        let themes = load_themes(config)?;
+        let mode = config.highlight_mode;
+        let default = build_syntect(copy_themes(&themes), config.syntect.theme.as_deref(), mode);
+        Ok(Self {
+            themes,
+            mode,
+            default: Arc::new(default),
+            by_theme: Default::default(),
+        })
+    }
+
+    /// names of every loaded theme, sorted
+    pub fn theme_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.themes.themes.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// the highlighter for `theme`, or the default one if it's `None` or
+    /// doesn't exist. themes don't apply when highlighting with classes
+    pub fn get(&self, theme: Option<&str>) -> Arc<SyntectAdapter> {
+        let Some(theme) = theme.filter(|_| self.mode == HighlightMode::Inline) else {
+            return Arc::clone(&self.default);
+        };
+        if let Some(adapter) = self.by_theme.read().unwrap().get(theme) {
+            return Arc::clone(adapter);
+        }
+        if !self.themes.themes.contains_key(theme) {
+            warn!("syntax highlighting theme {theme:?} not found, using the default");
+            return Arc::clone(&self.default);
+        }
+
+        let adapter = Arc::new(build_syntect(
+            copy_themes(&self.themes),
+            Some(theme),
+            self.mode,
+        ));
+        self.by_theme
+            .write()
+            .unwrap()
+            .insert(theme.into(), Arc::clone(&adapter));
+        adapter
+    }
+}
+
+/// how often to check if a renderer has exited
+const RENDERER_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+fn read_all(mut reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+fn run_renderer(bin: &Path, args: &[&str], input: &str, timeout: Duration) -> io::Result<String> {
+    let mut child = Command::new(bin)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| io::Error::new(err.kind(), format!("failed to spawn {bin:?}: {err}")))?;
+
+    // write and read from other threads so a renderer writing before it's
+    // done reading can't deadlock us. they aren't joined on timeout, as
+    // whatever the renderer started may still hold the pipes open
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_owned();
+    std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let stdout = child.stdout.take().unwrap();
+    let stdout = std::thread::spawn(move || read_all(stdout));
+    let stderr = child.stderr.take().unwrap();
+    let stderr = std::thread::spawn(move || read_all(stderr));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{bin:?} timed out after {timeout:?}"),
+            ));
+        }
+        std::thread::sleep(RENDERER_POLL_INTERVAL);
+    };
+    let join = |handle: std::thread::JoinHandle<io::Result<Vec<u8>>>| {
+        handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("reader thread panicked")))
+    };
+    let (stdout, stderr) = (join(stdout)?, join(stderr)?);
+
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{bin:?} exited with {status}: {}",
+            String::from_utf8_lossy(&stderr).trim()
+        )));
+    }
+
+    String::from_utf8(stdout).map_err(io::Error::other)
+}
+
+fn render_block(
+    lang: &str,
+    code: &str,
+    config: &MarkdownRenderConfig,
+) -> Option<io::Result<String>> {
+    let ssr = &config.ssr;
+    let timeout = Duration::from_millis(ssr.timeout_ms.get());
+    let rendered = match lang {
+        "math" | "latex" if config.math_ssr => {
+            run_renderer(&ssr.katex, &["--display-mode"], code, timeout)
+                .map(|html| format!("<div class=\"math math-display\">{html}</div>\n"))
+        }
+        "mermaid" if config.diagram_ssr => run_renderer(
+            &ssr.mermaid,
+            &["-i", "-", "-o", "-", "-e", "svg"],
+            code,
+            timeout,
+        )
+        .map(|svg| format!("<div class=\"diagram\">{svg}</div>\n")),
+        "dot" | "graphviz" if config.diagram_ssr => {
+            run_renderer(&ssr.dot, &["-Tsvg"], code, timeout)
+                .map(|svg| format!("<div class=\"diagram\">{svg}</div>\n"))
+        }
+        _ => return None,
+    };
+    Some(rendered)
+}
+
+/// replaces math and diagram code blocks with their server-side rendered
+/// output, leaving blocks that fail to render for the client to deal with
+fn render_server_side<'a>(root: &'a AstNode<'a>, config: &MarkdownRenderConfig) {
+    for node in root.descendants() {
+        let mut data = node.data.borrow_mut();
+        let NodeValue::CodeBlock(ref block) = data.value else {
+            continue;
+        };
+        let lang = block.info.split_whitespace().next().unwrap_or_default();
+
+        match render_block(lang, &block.literal, config) {
+            Some(Ok(html)) => {
+                debug!("rendered {lang} block server-side");
+                data.value = NodeValue::Raw(html);
+            }
+            Some(Err(err)) => warn!("failed to render {lang} block server-side: {err}"),
+            None => {}
+        }
+    }
+}
+
+/// turns mermaid code blocks into `<pre class="mermaid">`, which mermaid.js
+/// renders on the client
+fn pass_through_mermaid<'a>(root: &'a AstNode<'a>) {
+    for node in root.descendants() {
+        let mut data = node.data.borrow_mut();
+        let NodeValue::CodeBlock(ref block) = data.value else {
+            continue;
+        };
+        if block.info.split_whitespace().next() != Some("mermaid") {
+            continue;
+        }
+
+        let mut html = b"<pre class=\"mermaid\">".to_vec();
+        escape(&mut html, block.literal.as_bytes()).expect("writing to a Vec to never fail");
+        html.extend_from_slice(b"</pre>\n");
+
+        data.value = NodeValue::Raw(String::from_utf8(html).expect("escaped code to be UTF-8"));
+    }
+}
+
+/// options after the language in a fence's info string, like
+/// ` ```rust max-lines=20 `. unknown ones are ignored
+#[derive(Default)]
+struct FenceOptions {
+    max_lines: Option<usize>,
+}
+
+impl FenceOptions {
+    fn parse(info: &str) -> Self {
+        let mut options = Self::default();
+        for (key, value) in info
+            .split_whitespace()
+            .skip(1)
+            .filter_map(|option| option.split_once('='))
+        {
+            if key == "max-lines" {
+                options.max_lines = value.parse().ok().filter(|&n| n > 0);
+            }
+        }
+        options
+    }
+}
+
+fn write_code_block(
+    output: &mut dyn Write,
+    lang: &str,
+    code: &str,
+    syntect: Option<&dyn SyntaxHighlighterAdapter>,
+) -> io::Result<()> {
+    let mut code_attributes = HashMap::new();
+    if !lang.is_empty() {
+        code_attributes.insert(String::from("class"), format!("language-{lang}"));
+    }
+    match syntect {
+        Some(syntect) => {
+            syntect.write_pre_tag(output, HashMap::new())?;
+            syntect.write_code_tag(output, code_attributes)?;
+            syntect.write_highlighted(output, Some(lang).filter(|lang| !lang.is_empty()), code)?;
+        }
+        None => {
+            output.write_all(b"<pre>")?;
+            write_opening_tag(output, "code", code_attributes)?;
+            escape(output, code.as_bytes())?;
+        }
+    }
+    output.write_all(b"</code></pre>\n")
+}
+
+/// cuts code blocks with `max-lines=N` down to their first N lines, with the
+/// rest in a `<details>` so they can be expanded without javascript.
+/// the rest is highlighted on its own, so it may start out a bit off
+fn fold_code_blocks<'a>(root: &'a AstNode<'a>, syntect: Option<&dyn SyntaxHighlighterAdapter>) {
+    for node in root.descendants() {
+        let mut data = node.data.borrow_mut();
+        let NodeValue::CodeBlock(ref block) = data.value else {
+            continue;
+        };
+        let Some(max_lines) = FenceOptions::parse(&block.info).max_lines else {
+            continue;
+        };
+        let Some((split, _)) = block.literal.match_indices('\n').nth(max_lines - 1) else {
+            continue;
+        };
+        let (shown, rest) = block.literal.split_at(split + 1);
+        if rest.is_empty() {
+            continue;
+        }
+        let lang = block.info.split_whitespace().next().unwrap_or_default();
+        let hidden = rest.lines().count();
+
+        let mut html = Vec::new();
+        write_code_block(&mut html, lang, shown, syntect).expect("writing to a Vec to never fail");
+        html.extend_from_slice(
+            format!(
+                "<details class=\"code-fold\"><summary>show {hidden} more line{}</summary>\n",
+                if hidden == 1 { "" } else { "s" }
+            )
+            .as_bytes(),
+        );
+        write_code_block(&mut html, lang, rest, syntect).expect("writing to a Vec to never fail");
+        html.extend_from_slice(b"</details>\n");
+
+        data.value = NodeValue::Raw(String::from_utf8(html).expect("highlighted code to be UTF-8"));
+    }
+}
+
+/// wraps inline and display math in `<span class="math">` so client-side
+/// renderers like KaTeX's auto-render can find it
+fn mark_math<'a>(root: &'a AstNode<'a>) {
+    for node in root.descendants() {
+        let mut data = node.data.borrow_mut();
+        let NodeValue::Math(ref math) = data.value else {
+            continue;
+        };
+        let style = if math.display_math {
+            "display"
+        } else {
+            "inline"
+        };
+
+        let mut html = format!("<span class=\"math math-{style}\">").into_bytes();
+        escape(&mut html, math.literal.as_bytes()).expect("writing to a Vec to never fail");
+        html.extend_from_slice(b"</span>");
+
+        data.value = NodeValue::Raw(String::from_utf8(html).expect("escaped math to be UTF-8"));
+    }
+}
+
+/// prefixes root-relative link and image urls with the base path
+fn rewrite_urls<'a>(root: &'a AstNode<'a>, base_path: &str) {
+    for node in root.descendants() {
+        if let NodeValue::Link(ref mut link) | NodeValue::Image(ref mut link) =
+            node.data.borrow_mut().value
+            && let Cow::Owned(url) = with_base_path(base_path, &link.url)
+        {
+            link.url = url;
+        }
     }
-    Ok(builder.build())
 }
 
 pub fn render(
     markdown: &str,
     config: &MarkdownRenderConfig,
     syntect: Option<&dyn SyntaxHighlighterAdapter>,
+    base_path: &str,
 ) -> String {
     let mut options = ComrakOptions::default();
     options.extension.table = true;
@@ -39,6 +374,9 @@ pub fn render(
     options.extension.strikethrough = true;
     options.extension.multiline_block_quotes = true;
     options.extension.header_ids = Some(String::new());
+    options.extension.math_dollars = config.math;
+    options.extension.math_code = config.math;
+    options.extension.footnotes = config.footnotes;
     options.render.escape = config.escape;
     options.render.unsafe_ = config.unsafe_;
 
@@ -49,5 +387,95 @@ pub fn render(
 
     let plugins = Plugins::builder().render(render_plugins).build();
 
-    markdown_to_html_with_plugins(markdown, &options, &plugins)
+    let arena = Arena::new();
+    let root = parse_document(&arena, markdown, &options);
+
+    if !base_path.is_empty() {
+        rewrite_urls(root, base_path);
+    }
+
+    if config.math {
+        mark_math(root);
+    }
+
+    if config.math_ssr || config.diagram_ssr {
+        render_server_side(root, config);
+    }
+
+    // after server-side rendering, so that takes precedence
+    if config.mermaid {
+        pass_through_mermaid(root);
+    }
+
+    fold_code_blocks(root, syntect);
+
+    let mut html = Vec::new();
+    format_html_with_plugins(root, &options, &mut html, &plugins)
+        .expect("writing to a Vec to never fail");
+    String::from_utf8(html).expect("comrak to output valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOOTNOTED: &str = "bingus[^1] is a cat\n\n[^1]: a very good one\n";
+
+    #[test]
+    fn footnotes() {
+        let config = MarkdownRenderConfig {
+            footnotes: true,
+            ..Default::default()
+        };
+        let html = render(FOOTNOTED, &config, None, "");
+
+        assert!(html.contains(r#"<sup class="footnote-ref">"#), "{html}");
+        assert!(html.contains(r#"<section class="footnotes""#), "{html}");
+        assert!(html.contains("a very good one"), "{html}");
+        assert!(html.contains(r#"class="footnote-backref""#), "{html}");
+    }
+
+    #[test]
+    fn footnotes_disabled() {
+        let html = render(FOOTNOTED, &MarkdownRenderConfig::default(), None, "");
+
+        assert!(!html.contains("footnote"), "{html}");
+    }
+
+    #[test]
+    fn hanging_renderer_times_out() {
+        let start = Instant::now();
+        let err = run_renderer(
+            Path::new("sh"),
+            &["-c", "sleep 10"],
+            "",
+            Duration::from_millis(100),
+        )
+        .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hanging_renderer_leaves_code_block() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dot = std::env::temp_dir().join(format!("bingus-blog-dot-{}", std::process::id()));
+        std::fs::write(&dot, "#!/bin/sh\nsleep 10\n").unwrap();
+        std::fs::set_permissions(&dot, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut config = MarkdownRenderConfig {
+            diagram_ssr: true,
+            ..Default::default()
+        };
+        config.ssr.dot = dot.clone().into();
+        config.ssr.timeout_ms = 100.try_into().unwrap();
+        let html = render("```dot\ndigraph { a -> b }\n```\n", &config, None, "");
+        std::fs::remove_file(&dot).unwrap();
+
+        assert!(html.contains("<pre"), "{html}");
+        assert!(html.contains("digraph { a -&gt; b }"), "{html}");
+    }
 }
@@ -1,3 +1,7 @@
+use std::borrow::Cow;
+use std::path::Path as FsPath;
+
+use arc_swap::access::DynAccess;
 use axum::extract::rejection::PathRejection;
 use axum::extract::{FromRequestParts, Path};
 use axum::http::request::Parts;
@@ -5,6 +9,30 @@ use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use serde::de::DeserializeOwned;
 
+pub type BasePathAccess = Box<dyn DynAccess<Box<str>> + Send + Sync>;
+
+/// prefixes root-relative urls with the base path the blog is served under
+pub fn with_base_path<'a>(base_path: &str, url: &'a str) -> Cow<'a, str> {
+    if base_path.is_empty() || !url.starts_with('/') || url.starts_with("//") {
+        Cow::Borrowed(url)
+    } else {
+        Cow::Owned(format!("{base_path}{url}"))
+    }
+}
+
+/// the name of a post file relative to `root`, without its extension and
+/// with `/` between directories, e.g. `2024/hello` for `posts/2024/hello.md`
+pub fn relative_name(root: &FsPath, path: &FsPath) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?.with_extension("");
+    let components: Vec<_> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    (!components.is_empty()).then(|| components.join("/"))
+}
+
+/// a path extractor that allows `/` between segments, but no empty, `.` or
+/// `..` segments
 pub struct SafePath<T>(pub T);
 
 impl<S, T> FromRequestParts<S> for SafePath<T>
@@ -19,7 +47,13 @@ where
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let s = Path::<T>::from_request_parts(parts, state).await?.0;
 
-        if s.as_ref().contains("..") || s.as_ref().contains('/') {
+        let path = s.as_ref();
+        if path.contains("..")
+            || path.contains('\\')
+            || path
+                .split('/')
+                .any(|segment| segment.is_empty() || segment == ".")
+        {
             return Err(SafePathRejection::Invalid);
         }
 
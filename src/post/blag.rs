@@ -1,34 +1,42 @@
 use std::collections::BTreeSet;
 use std::hash::{DefaultHasher, Hash, Hasher};
-use std::path::Path;
-use std::process::Stdio;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
 use std::sync::Arc;
 use std::time::Duration;
 
 use arc_swap::access::Access;
 use async_trait::async_trait;
-use axum::http::HeaderValue;
+use axum::http::{HeaderMap, HeaderValue};
 use chrono::{DateTime, Utc};
-use futures::stream::FuturesUnordered;
+use futures::stream;
 use futures::{FutureExt, StreamExt};
 use indexmap::IndexMap;
 use serde::Deserialize;
 use serde_value::Value;
 use tokio::fs::OpenOptions;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
 use tokio::time::Instant;
 use tracing::{debug, error, info, instrument};
 
 use crate::config::BlagConfig;
 use crate::error::PostError;
-use crate::post::Filter;
+use crate::path::BasePathAccess;
+use crate::post::PostFilter;
 use crate::systemtime_as_secs::as_secs;
+use crate::text::{reading_time, word_count};
 
-use super::cache::{CacheGuard, CacheValue};
-use super::{ApplyFilters, PostManager, PostMetadata, RenderStats, ReturnedPost};
+use super::cache::{mtimes, CacheGuard, CacheStats, CacheValue};
+use super::slugs::{PostLinks, SlugConflictAccess, SlugIndex};
+use super::{ApplyFilters, PostManager, PostMetadata, RenderStats, ReturnedPost, StyleAccess};
 
 #[derive(Deserialize, Debug)]
 struct BlagMetadata {
+    pub slug: Option<Arc<str>>,
+    #[serde(default)]
+    pub aliases: Vec<Arc<str>>,
     pub title: Arc<str>,
     pub description: Arc<str>,
     pub author: Arc<str>,
@@ -40,6 +48,15 @@ struct BlagMetadata {
     pub modified_at: Option<DateTime<Utc>>,
     #[serde(default)]
     pub tags: BTreeSet<Arc<str>>,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub pinned: bool,
+    pub series: Option<Arc<str>>,
+    pub series_index: Option<u32>,
+    pub publish_at: Option<DateTime<Utc>>,
+    pub unpublish_at: Option<DateTime<Utc>>,
+    pub template: Option<Arc<str>>,
     pub dont_cache: bool,
     pub raw: Option<Arc<str>>,
 }
@@ -49,6 +66,8 @@ impl BlagMetadata {
         (
             PostMetadata {
                 name,
+                slug: self.slug,
+                aliases: self.aliases,
                 title: self.title,
                 description: self.description,
                 author: self.author,
@@ -58,6 +77,20 @@ impl BlagMetadata {
                 written_at: self.written_at,
                 modified_at: self.modified_at,
                 tags: self.tags.into_iter().collect(),
+                draft: self.draft,
+                pinned: self.pinned,
+                series: self.series,
+                series_index: self.series_index,
+                publish_at: self.publish_at,
+                unpublish_at: self.unpublish_at,
+                template: self.template,
+                raw_access: None,
+                excerpt_html: None,
+                state: Default::default(),
+                reading_time_minutes: None,
+                word_count: None,
+                body_bytes: 0,
+                content_hash: Arc::from(""),
             },
             self.dont_cache,
             self.raw,
@@ -68,9 +101,63 @@ impl BlagMetadata {
 pub struct Blag<A> {
     config: A,
     cache: Option<Arc<CacheGuard>>,
+    slug_conflict: SlugConflictAccess,
+    style: StyleAccess,
+    base_path: BasePathAccess,
+    slug_index: SlugIndex,
+    /// caps how many scripts run at once, across all requests
+    permits: Semaphore,
+    /// how many permits there are, for listings to not queue more
+    max_concurrency: usize,
     _fastblag: bool,
 }
 
+/// how much of the end of a script's stderr is kept for error messages
+const STDERR_TAIL: usize = 2048;
+
+/// reads all of `reader`, keeping only the last `cap` bytes
+async fn read_tail(mut reader: impl AsyncRead + Unpin, cap: usize) -> std::io::Result<Vec<u8>> {
+    let mut tail = Vec::new();
+    let mut chunk = [0; 1024];
+    loop {
+        let n = reader.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(tail);
+        }
+        tail.extend_from_slice(&chunk[..n]);
+        if tail.len() > cap {
+            tail.drain(..tail.len() - cap);
+        }
+    }
+}
+
+/// describes a failed script run, with what it printed to stderr
+async fn script_error(
+    status: ExitStatus,
+    stderr: JoinHandle<std::io::Result<Vec<u8>>>,
+) -> PostError {
+    let stderr = match stderr.await {
+        Ok(Ok(stderr)) => stderr,
+        _ => Vec::new(),
+    };
+    let stderr = String::from_utf8_lossy(&stderr);
+    match stderr.trim() {
+        "" => PostError::RenderError(status.to_string()),
+        stderr => PostError::RenderError(format!("{status}: {stderr}")),
+    }
+}
+
+/// turns a header name into the name of the variable it's passed as
+fn header_var(header: &str) -> String {
+    let mut var = String::from("BLAG_HEADER_");
+    var.extend(header.chars().map(|c| match c {
+        'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+        _ => '_',
+    }));
+    var
+}
+
+#[allow(clippy::large_enum_variant)] // Normal is by far the most common
 enum RenderResult {
     Normal(PostMetadata, String, (Duration, Duration), bool),
     Raw(Vec<u8>, Arc<str>),
@@ -82,10 +169,24 @@ where
     A: Sync,
     A::Guard: Send,
 {
-    pub fn new(config: A, cache: Option<Arc<CacheGuard>>) -> Self {
+    pub fn new(
+        config: A,
+        cache: Option<Arc<CacheGuard>>,
+        slug_conflict: SlugConflictAccess,
+        style: StyleAccess,
+        base_path: BasePathAccess,
+    ) -> Self {
+        let max_concurrency = config.load().max_concurrency.get();
+        let permits = Semaphore::new(max_concurrency);
         Self {
             config,
             cache,
+            slug_conflict,
+            style,
+            base_path,
+            slug_index: Default::default(),
+            permits,
+            max_concurrency,
             _fastblag: false,
         }
     }
@@ -95,17 +196,39 @@ where
         name: Arc<str>,
         path: impl AsRef<Path>,
         query_json: String,
+        headers: Vec<(String, String)>,
     ) -> Result<RenderResult, PostError> {
+        let _permit = match self.permits.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                debug!(%name, "waiting for a permit to render");
+                self.permits
+                    .acquire()
+                    .await
+                    .expect("semaphore to never be closed")
+            }
+        };
+
         let start = Instant::now();
-        let bin = self.config.load().bin.clone();
+        let (bin, timeout) = {
+            let config = self.config.load();
+            (
+                config.bin.clone(),
+                Duration::from_millis(config.timeout_ms.get()),
+            )
+        };
+        let base_path = self.base_path.load().clone();
 
         debug!(%name, "rendering");
 
-        let mut cmd = tokio::process::Command::new(&*bin)
+        let mut child = tokio::process::Command::new(&*bin)
             .arg(path.as_ref())
             .env("BLAG_QUERY", query_json)
+            .env("BLAG_POST_NAME", &*name)
+            .envs(headers)
+            .env("BLAG_BASE_PATH", &*base_path)
             .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
+            .stderr(Stdio::piped())
             .stdin(Stdio::null())
             .spawn()
             .map_err(|err| {
@@ -113,43 +236,70 @@ where
                 err
             })?;
 
-        let stdout = cmd.stdout.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        // read alongside stdout, so a chatty script can't fill the pipe and block
+        let stderr = tokio::spawn(read_tail(child.stderr.take().unwrap(), STDERR_TAIL));
 
-        let mut reader = BufReader::new(stdout);
-        let mut buf = String::new();
-        reader.read_line(&mut buf).await?;
+        let output = async {
+            let mut reader = BufReader::new(stdout);
+            let mut buf = String::new();
+            reader.read_line(&mut buf).await?;
 
-        let blag_meta: BlagMetadata = serde_json::from_str(&buf)?;
-        debug!("blag meta: {blag_meta:?}");
-        let (meta, dont_cache, raw) = blag_meta.into_full(name);
-        buf.clear();
+            // no metadata, the script most likely failed before printing anything
+            if buf.is_empty() {
+                let status = child.wait().await?;
+                return Err(script_error(status, stderr).await);
+            }
 
-        // this is morally reprehensible
-        if let Some(raw) = raw {
-            let mut buf = buf.into_bytes();
-            reader.read_to_end(&mut buf).await?;
-            return Ok(RenderResult::Raw(buf, raw));
-        }
+            let blag_meta: BlagMetadata = serde_json::from_str(&buf)?;
+            debug!("blag meta: {blag_meta:?}");
+            let (mut meta, dont_cache, raw) = blag_meta.into_full(name);
+            buf.clear();
 
-        let parsed = start.elapsed();
-        let rendering = Instant::now();
+            // this is morally reprehensible
+            if let Some(raw) = raw {
+                let mut buf = buf.into_bytes();
+                reader.read_to_end(&mut buf).await?;
+                return Ok(RenderResult::Raw(buf, raw));
+            }
 
-        reader.read_to_string(&mut buf).await?;
+            let parsed = start.elapsed();
+            let rendering = Instant::now();
 
-        let status = cmd.wait().await?;
-        debug!("exited: {status}");
-        if !status.success() {
-            return Err(PostError::RenderError(status.to_string()));
-        }
+            reader.read_to_string(&mut buf).await?;
+
+            let status = child.wait().await?;
+            debug!("exited: {status}");
+            if !status.success() {
+                return Err(script_error(status, stderr).await);
+            }
+
+            let rendered = rendering.elapsed();
+
+            let words = word_count(&buf);
+            meta.word_count = Some(words);
+            meta.reading_time_minutes = reading_time(words, self.style.load().words_per_minute);
+            meta.describe_body(&buf);
 
-        let rendered = rendering.elapsed();
+            Ok(RenderResult::Normal(
+                meta,
+                buf,
+                (parsed, rendered),
+                dont_cache,
+            ))
+        };
 
-        Ok(RenderResult::Normal(
-            meta,
-            buf,
-            (parsed, rendered),
-            dont_cache,
-        ))
+        let result = tokio::time::timeout(timeout, output).await;
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                // kill() also waits on the child, so it doesn't linger as a zombie
+                if let Err(err) = child.kill().await {
+                    error!("failed to kill {bin:?}: {err}");
+                }
+                Err(PostError::RenderError("timed out".into()))
+            }
+        }
     }
 
     fn as_raw(name: &str) -> String {
@@ -163,23 +313,10 @@ where
     fn is_raw(name: &str) -> bool {
         name.ends_with(".sh")
     }
-}
-
-#[async_trait]
-impl<A> PostManager for Blag<A>
-where
-    A: Access<BlagConfig>,
-    A: Sync,
-    A::Guard: Send,
-{
-    async fn get_all_posts(
-        &self,
-        filters: &[Filter<'_>],
-        query: &IndexMap<String, Value>,
-    ) -> Result<Vec<(PostMetadata, Arc<str>, RenderStats)>, PostError> {
-        let root = &self.config.load().root;
 
-        let mut set = FuturesUnordered::new();
+    /// the scripts in the root, by post name
+    async fn scan(&self) -> Result<Vec<(Arc<str>, PathBuf)>, PostError> {
+        let root = self.config.load().root.clone();
         let mut posts = Vec::new();
         let mut files = tokio::fs::read_dir(&root).await?;
 
@@ -205,51 +342,44 @@ where
 
                 if Self::is_raw(&name) {
                     name.truncate(name.len() - 3);
-                    let name = name.into();
-                    set.push(self.get_post(Arc::clone(&name), query).map(|v| (name, v)));
-                }
-            }
-        }
-
-        while let Some((name, result)) = set.next().await {
-            let post = match result {
-                Ok(v) => v,
-                Err(err) => {
-                    error!("error while rendering blagpost {name:?}: {err}");
-                    continue;
+                    posts.push((name.into(), entry.path()));
                 }
-            };
-
-            if let ReturnedPost::Rendered {
-                meta, body, perf, ..
-            } = post
-                && meta.apply_filters(filters)
-            {
-                posts.push((meta, body, perf));
             }
         }
 
-        debug!("collected posts");
-
         Ok(posts)
     }
 
+    /// what a render for `query_json` and `headers` is cached under
+    fn query_hash(query_json: &str, headers: &[(String, String)]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        query_json.hash(&mut hasher);
+        headers.hash(&mut hasher);
+        hasher.finish()
+    }
+
     #[instrument(skip(self))]
-    async fn get_post(
+    async fn get_post_by_name(
         &self,
         name: Arc<str>,
         query: &IndexMap<String, Value>,
+        headers: &HeaderMap,
     ) -> Result<ReturnedPost, PostError> {
+        // only the top level of the root is served, don't run anything else
+        if name.contains('/') {
+            return Err(PostError::NotFound(name));
+        }
+
         let start = Instant::now();
         let BlagConfig {
             ref root,
             ref raw_access,
+            ref forward_headers,
             ..
         } = &*self.config.load();
 
         if Self::is_raw(&name) {
-            let mut buffer = Vec::new();
-            let mut file = OpenOptions::new()
+            let file = OpenOptions::new()
                 .read(true)
                 .open(root.join(&*name))
                 .await
@@ -257,12 +387,9 @@ where
                     std::io::ErrorKind::NotFound => PostError::NotFound(name),
                     _ => PostError::IoError(err),
                 })?;
-            file.read_to_end(&mut buffer).await?;
 
-            return Ok(ReturnedPost::Raw {
-                buffer,
-                content_type: HeaderValue::from_static("text/x-shellscript"),
-            });
+            return ReturnedPost::raw_file(file, HeaderValue::from_static("text/x-shellscript"))
+                .await;
         }
 
         let raw_name = Self::as_raw(&name);
@@ -282,12 +409,19 @@ where
 
         let mtime = as_secs(stat.modified()?);
 
+        // only what's explicitly listed, other headers may hold credentials
+        let headers: Vec<(String, String)> = forward_headers
+            .iter()
+            .filter_map(|header| {
+                let value = headers.get(&**header)?.to_str().ok()?;
+                Some((header_var(header), value.to_owned()))
+            })
+            .collect();
+
         let query_json = serde_json::to_string(&query).expect("this should not fail");
-        let mut hasher = DefaultHasher::new();
-        query_json.hash(&mut hasher);
-        let query_hash = hasher.finish();
+        let query_hash = Self::query_hash(&query_json, &headers);
 
-        let post = if let Some(cache) = &self.cache
+        let mut post = if let Some(cache) = &self.cache
             && let Some(CacheValue { meta, body, .. }) =
                 cache.lookup(name.clone(), mtime, query_hash).await
         {
@@ -296,17 +430,20 @@ where
                 body,
                 perf: RenderStats::Cached(start.elapsed()),
                 raw_name,
+                render_hash: query_hash,
             }
         } else {
             let (meta, content, (parsed, rendered), dont_cache) =
-                match self.render(name.clone(), path, query_json).await? {
+                match self.render(name.clone(), path, query_json, headers).await? {
                     RenderResult::Normal(x, y, z, w) => (x, y, z, w),
                     RenderResult::Raw(buffer, content_type) => {
                         return Ok(ReturnedPost::Raw {
-                            buffer,
+                            body: buffer.into(),
+                            length: None,
                             content_type: HeaderValue::from_str(&content_type)
                                 .map_err(Into::into)
                                 .map_err(PostError::Other)?,
+                            modified_at: None,
                         });
                     }
                 };
@@ -328,32 +465,277 @@ where
                     rendered,
                 },
                 raw_name,
+                render_hash: query_hash,
             }
         };
 
-        if let ReturnedPost::Rendered { perf, .. } = &post {
+        if let ReturnedPost::Rendered { meta, perf, .. } = &mut post {
+            meta.refresh_state();
             info!("rendered blagpost in {:?}", perf);
         }
 
         Ok(post)
     }
+}
+
+#[async_trait]
+impl<A> PostManager for Blag<A>
+where
+    A: Access<BlagConfig>,
+    A: Sync,
+    A::Guard: Send,
+{
+    async fn get_all_posts(
+        &self,
+        filters: &[Box<dyn PostFilter + '_>],
+        query: &IndexMap<String, Value>,
+    ) -> Result<Vec<(PostMetadata, Arc<str>, RenderStats)>, PostError> {
+        let scripts = self.scan().await?;
+        let mut posts = Vec::new();
+        let mut links = Vec::new();
+
+        // listings aren't rendered for any one request's headers
+        let headers = HeaderMap::new();
+        // every post is a subprocess, don't spawn them all at once
+        let mut set = stream::iter(scripts)
+            .map(|(name, _)| {
+                self.get_post_by_name(Arc::clone(&name), query, &headers)
+                    .map(|v| (name, v))
+            })
+            .buffer_unordered(self.max_concurrency);
+
+        while let Some((name, result)) = set.next().await {
+            let post = match result {
+                Ok(v) => v,
+                Err(err) => {
+                    error!("error while rendering blagpost {name:?}: {err}");
+                    continue;
+                }
+            };
+
+            if let ReturnedPost::Rendered {
+                meta, body, perf, ..
+            } = post
+            {
+                links.push(PostLinks::from(&meta));
+                if meta.apply_filters(filters) {
+                    posts.push((meta, body, perf));
+                }
+            }
+        }
+
+        self.slug_index.rebuild(links, *self.slug_conflict.load())?;
+        for (meta, ..) in &mut posts {
+            self.slug_index.verify(meta);
+        }
+
+        debug!("collected posts");
+
+        Ok(posts)
+    }
+
+    async fn get_all_post_metadata(
+        &self,
+        filters: &[Box<dyn PostFilter + '_>],
+        query: &IndexMap<String, Value>,
+    ) -> Result<Vec<PostMetadata>, PostError> {
+        let Some(cache) = &self.cache else {
+            // rendering is the only way to get the metadata then
+            return Ok(self
+                .get_all_posts(filters, query)
+                .await?
+                .into_iter()
+                .map(|(meta, ..)| meta)
+                .collect());
+        };
+
+        let scripts = self.scan().await?;
+        let mut posts = Vec::new();
+        let mut links = Vec::new();
+
+        // the same as get_all_posts, so its renders are found
+        let headers = HeaderMap::new();
+        let query_json = serde_json::to_string(&query).expect("this should not fail");
+        let query_hash = Self::query_hash(&query_json, &[]);
+        let mut set = stream::iter(scripts)
+            .map(|(name, path)| {
+                let headers = &headers;
+                async move {
+                    let mtime = as_secs(tokio::fs::metadata(&path).await?.modified()?);
+                    if let Some(meta) = cache.lookup_metadata(name.clone(), mtime, query_hash).await
+                    {
+                        return Ok(Some(meta));
+                    }
+
+                    // not rendered yet, or not cacheable
+                    match self.get_post_by_name(name, query, headers).await? {
+                        ReturnedPost::Rendered { meta, .. } => Ok(Some(meta)),
+                        ReturnedPost::Raw { .. } => Ok(None),
+                    }
+                }
+            })
+            .buffer_unordered(self.max_concurrency);
+
+        while let Some(result) = set.next().await {
+            let mut meta = match result {
+                Ok(Some(meta)) => meta,
+                Ok(None) => continue,
+                Err::<_, PostError>(err) => {
+                    error!("error while getting blagpost metadata: {err}");
+                    continue;
+                }
+            };
+
+            links.push(PostLinks::from(&meta));
+            if meta.apply_filters(filters) {
+                meta.refresh_state();
+                posts.push(meta);
+            }
+        }
+
+        self.slug_index.rebuild(links, *self.slug_conflict.load())?;
+        for meta in &mut posts {
+            self.slug_index.verify(meta);
+        }
+
+        Ok(posts)
+    }
+
+    async fn get_post(
+        &self,
+        name: Arc<str>,
+        query: &IndexMap<String, Value>,
+        headers: &HeaderMap,
+    ) -> Result<ReturnedPost, PostError> {
+        let mut result = match self
+            .get_post_by_name(Arc::clone(&name), query, headers)
+            .await
+        {
+            // filenames take precedence over slugs
+            Err(PostError::NotFound(_)) => {
+                let mut resolved = self.slug_index.resolve(&name);
+                // the slug may be new, or from a new post
+                if resolved.is_none() && self.slug_index.needs_build() {
+                    let _ = self.get_all_post_metadata(&[], query).await;
+                    resolved = self.slug_index.resolve(&name);
+                }
+                match resolved {
+                    Some(name) => self.get_post_by_name(name, query, headers).await,
+                    None => Err(PostError::NotFound(name)),
+                }
+            }
+            result => result,
+        };
+
+        if let Ok(ReturnedPost::Rendered { meta, .. }) = &mut result {
+            self.slug_index.verify(meta);
+        }
+
+        result
+    }
+
+    async fn resolve_alias(&self, name: &str) -> Option<Arc<str>> {
+        let resolved = self.slug_index.resolve_alias(name);
+        if resolved.is_none() && self.slug_index.needs_build() {
+            let _ = self.get_all_post_metadata(&[], &IndexMap::new()).await;
+            return self.slug_index.resolve_alias(name);
+        }
+        resolved
+    }
+
+    async fn root_ok(&self) -> bool {
+        let root = self.config.load().root.clone();
+        tokio::fs::read_dir(root).await.is_ok()
+    }
 
     async fn cleanup(&self) {
         if let Some(cache) = &self.cache {
-            let root = &self.config.load().root;
+            let root = self.config.load().root.clone();
+            let files = cache
+                .names()
+                .await
+                .into_iter()
+                .map(|name| {
+                    let path = root.join(Self::as_raw(&name));
+                    (name, path)
+                })
+                .collect();
+            let mtimes = mtimes(files).await;
+
             cache
-                .cleanup(|key, value| {
-                    let mtime = std::fs::metadata(root.join(Self::as_raw(&key.name)))
-                        .ok()
-                        .and_then(|metadata| metadata.modified().ok())
-                        .map(as_secs);
-
-                    match mtime {
-                        Some(mtime) => mtime <= value.mtime,
-                        None => false,
-                    }
+                .cleanup(|key, value| match mtimes.get(&key.name) {
+                    Some(&mtime) => mtime <= value.mtime,
+                    None => false,
                 })
                 .await
         }
     }
+
+    async fn clear_cache(&self) -> usize {
+        match &self.cache {
+            Some(cache) => cache.clear().await,
+            None => 0,
+        }
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU64;
+
+    use arc_swap::access::Map;
+    use arc_swap::ArcSwap;
+
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn stderr_tail_is_capped() {
+        let stderr: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+
+        let tail = read_tail(stderr.as_slice(), STDERR_TAIL).await.unwrap();
+        assert_eq!(tail.len(), 2048);
+        assert_eq!(tail, stderr[5000 - 2048..]);
+
+        let short = read_tail(&b"oops"[..], STDERR_TAIL).await.unwrap();
+        assert_eq!(short, b"oops");
+    }
+
+    #[tokio::test]
+    async fn render_times_out() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-blag-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("slow.sh"), "sleep 10\n").unwrap();
+
+        let mut config = Config::default();
+        config.engine.blag.root = root.clone().into();
+        config.engine.blag.bin = Path::new("sh").into();
+        config.engine.blag.timeout_ms = NonZeroU64::new(200).unwrap();
+        let swapper = Arc::new(ArcSwap::from_pointee(config));
+        let blag = Blag::new(
+            Map::new(swapper.clone(), |c: &Config| &c.engine.blag),
+            None,
+            Box::new(Map::new(swapper.clone(), |c: &Config| {
+                &c.engine.slug_conflict
+            })),
+            Box::new(Map::new(swapper.clone(), |c: &Config| &c.style)),
+            Box::new(Map::new(swapper.clone(), |c: &Config| &c.http.base_path)),
+        );
+
+        let start = Instant::now();
+        let result = blag
+            .get_post("slow".into(), &IndexMap::new(), &HeaderMap::new())
+            .await;
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(
+            matches!(&result, Err(PostError::RenderError(err)) if err == "timed out"),
+            "{result:?}"
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
 }
@@ -1,21 +1,26 @@
+use std::collections::{self, HashSet};
 use std::fmt::Debug;
 use std::io::{Read, Write};
-use std::num::NonZeroU64;
+use std::num::{NonZeroU64, NonZeroUsize};
 use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::SystemTime;
 
-use crate::config::CacheConfig;
+use crate::config::{CacheConfig, Compression};
 use crate::post::PostMetadata;
 use arc_swap::access::DynAccess;
 use color_eyre::eyre::{self, Context};
+use metrics::counter;
 use scc::HashMap;
 use serde::{Deserialize, Serialize};
 use tokio::io::AsyncReadExt;
-use tracing::{debug, info, instrument, trace, Span};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, instrument, trace, Span};
 
 /// do not persist cache if this version number changed
-pub const CACHE_VERSION: u16 = 5;
+pub const CACHE_VERSION: u16 = 18;
 
 fn now() -> u128 {
     crate::systemtime_as_secs::as_millis(SystemTime::now())
@@ -28,14 +33,51 @@ pub struct CacheValue {
     pub mtime: u64,
     /// when the item was inserted into cache, in milliseconds since epoch
     pub cached_at: u128,
+    /// when the item was last looked up, in milliseconds since epoch
+    pub last_accessed: u128,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+impl CacheValue {
+    /// approximate size of the entry when serialized, in bytes
+    pub fn size(&self) -> usize {
+        self.body.len() + bitcode::serialize(&self.meta).map_or(0, |meta| meta.len())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Cache {
     map: HashMap<CacheKey, CacheValue>,
     version: u16,
     #[serde(skip)]
     ttl: Option<NonZeroU64>,
+    #[serde(skip)]
+    max_entries: Option<NonZeroUsize>,
+    #[serde(skip)]
+    max_bytes: Option<NonZeroUsize>,
+    /// running total of [`CacheValue::size`] over all entries
+    #[serde(skip)]
+    bytes: AtomicUsize,
+    #[serde(skip)]
+    persistence: bool,
+    #[serde(skip)]
+    hits: AtomicU64,
+    #[serde(skip)]
+    misses: AtomicU64,
+    /// whether entries were added or removed since the last save
+    #[serde(skip)]
+    dirty: AtomicBool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    /// since startup
+    pub hits: u64,
+    /// since startup
+    pub misses: u64,
+    pub version: u16,
+    pub persistence: bool,
 }
 
 #[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Clone, Debug)]
@@ -46,14 +88,36 @@ pub struct CacheKey {
 }
 
 impl Cache {
-    pub fn new(ttl: Option<NonZeroU64>) -> Self {
+    pub fn new(config: &CacheConfig) -> Self {
         Cache {
             map: Default::default(),
             version: CACHE_VERSION,
-            ttl,
+            ttl: config.ttl,
+            max_entries: config.max_entries,
+            max_bytes: config.max_bytes,
+            bytes: AtomicUsize::new(0),
+            persistence: config.persistence,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            dirty: AtomicBool::new(false),
         }
     }
 
+    fn removed(&self, value: &CacheValue) {
+        self.bytes.fetch_sub(value.size(), Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        counter!("bingus_cache_hits_total").increment(1);
+    }
+
+    fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        counter!("bingus_cache_misses_total").increment(1);
+    }
+
     fn up_to_date(&self, cached: &CacheValue, mtime: u64) -> bool {
         mtime <= cached.mtime
             && self
@@ -65,20 +129,26 @@ impl Cache {
     pub async fn lookup(&self, name: Arc<str>, mtime: u64, extra: u64) -> Option<CacheValue> {
         trace!("looking up in cache");
         match self.map.get_async(&CacheKey { name, extra }).await {
-            Some(entry) => {
-                let cached = entry.get();
+            Some(mut entry) => {
+                let cached = entry.get_mut();
                 Span::current().record("entry_mtime", cached.mtime);
                 trace!("found in cache");
                 if self.up_to_date(cached, mtime) {
                     trace!("entry up-to-date");
+                    self.hit();
+                    cached.last_accessed = now();
                     Some(cached.clone())
                 } else {
-                    let _ = entry.remove();
+                    self.removed(&entry.remove());
                     debug!("removed stale entry");
+                    self.miss();
                     None
                 }
             }
-            None => None,
+            None => {
+                self.miss();
+                None
+            }
         }
     }
 
@@ -91,19 +161,25 @@ impl Cache {
     ) -> Option<PostMetadata> {
         trace!("looking up metadata in cache");
         match self.map.get_async(&CacheKey { name, extra }).await {
-            Some(entry) => {
-                let cached = entry.get();
+            Some(mut entry) => {
+                let cached = entry.get_mut();
                 Span::current().record("entry_mtime", cached.mtime);
                 if self.up_to_date(cached, mtime) {
                     trace!("entry up-to-date");
+                    self.hit();
+                    cached.last_accessed = now();
                     Some(cached.meta.clone())
                 } else {
-                    let _ = entry.remove();
+                    self.removed(&entry.remove());
                     debug!("removed stale entry");
+                    self.miss();
                     None
                 }
             }
-            None => None,
+            None => {
+                self.miss();
+                None
+            }
         }
     }
 
@@ -118,18 +194,20 @@ impl Cache {
     ) -> Option<CacheValue> {
         trace!("inserting into cache");
 
-        let r = self
-            .map
-            .upsert_async(
-                CacheKey { name, extra },
-                CacheValue {
-                    meta: metadata,
-                    body: rendered,
-                    mtime,
-                    cached_at: now(),
-                },
-            )
-            .await;
+        let now = now();
+        let value = CacheValue {
+            meta: metadata,
+            body: rendered,
+            mtime,
+            cached_at: now,
+            last_accessed: now,
+        };
+        self.bytes.fetch_add(value.size(), Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Relaxed);
+        let r = self.map.upsert_async(CacheKey { name, extra }, value).await;
+        if let Some(old) = &r {
+            self.removed(old);
+        }
 
         debug!(
             "{} cache",
@@ -139,15 +217,86 @@ impl Cache {
             }
         );
 
+        if let Some(max_entries) = self.max_entries {
+            let len = self.len();
+            if len > max_entries.get() {
+                self.evict_lru(len - max_entries.get()).await;
+            }
+        }
+
+        if let Some(max_bytes) = self.max_bytes {
+            let bytes = self.bytes();
+            if bytes > max_bytes.get() {
+                self.evict_oldest(bytes - max_bytes.get()).await;
+            }
+        }
+
         r
     }
 
+    /// removes the `n` least recently used entries
+    async fn evict_lru(&self, n: usize) {
+        let mut entries = Vec::with_capacity(self.len());
+        self.map
+            .scan_async(|k, v| entries.push((k.clone(), v.last_accessed)))
+            .await;
+        entries.sort_unstable_by_key(|(_, last_accessed)| *last_accessed);
+
+        let mut evicted = 0;
+        for (key, last_accessed) in entries.into_iter().take(n) {
+            // skip entries that were used or replaced since the scan
+            if let Some((_, value)) = self
+                .map
+                .remove_if_async(&key, |v| v.last_accessed == last_accessed)
+                .await
+            {
+                self.removed(&value);
+                evicted += 1;
+            }
+        }
+
+        debug!("evicted {evicted} least recently used entries");
+    }
+
+    /// removes the oldest entries until at least `bytes` bytes are freed
+    async fn evict_oldest(&self, bytes: usize) {
+        let mut entries = Vec::with_capacity(self.len());
+        self.map
+            .scan_async(|k, v| entries.push((k.clone(), v.cached_at)))
+            .await;
+        entries.sort_unstable_by_key(|(_, cached_at)| *cached_at);
+
+        let mut evicted = 0;
+        let mut freed = 0;
+        for (key, cached_at) in entries {
+            if freed >= bytes {
+                break;
+            }
+            // skip entries that were replaced since the scan
+            if let Some((_, value)) = self
+                .map
+                .remove_if_async(&key, |v| v.cached_at == cached_at)
+                .await
+            {
+                let size = value.size();
+                self.bytes.fetch_sub(size, Ordering::Relaxed);
+                freed += size;
+                evicted += 1;
+            }
+        }
+
+        debug!("evicted {evicted} oldest entries ({freed} bytes)");
+    }
+
     #[instrument(level = "debug", skip(self))]
     #[allow(unused)]
     pub async fn remove(&self, name: Arc<str>, extra: u64) -> Option<(CacheKey, CacheValue)> {
         trace!("removing from cache");
 
         let r = self.map.remove_async(&CacheKey { name, extra }).await;
+        if let Some((_, value)) = &r {
+            self.removed(value);
+        }
 
         debug!(
             "item {} cache",
@@ -164,14 +313,15 @@ impl Cache {
         let old_size = self.map.len();
         let mut i = 0;
 
-        // TODO: multithread
-        // not urgent as this is run concurrently anyways
+        // predicates are expected to be cheap, anything that touches the
+        // filesystem should be gathered beforehand (see `mtimes`)
         self.map
             .retain_async(|k, v| {
                 if predicate(k, v) {
                     true
                 } else {
                     debug!("removing {k:?} from cache");
+                    self.removed(v);
                     i += 1;
                     false
                 }
@@ -182,6 +332,22 @@ impl Cache {
         debug!("removed {i} entries ({old_size} -> {new_size} entries)");
     }
 
+    /// removes every entry, returning how many were removed
+    #[instrument(level = "debug", skip(self))]
+    pub async fn clear(&self) -> usize {
+        let mut i = 0;
+        self.map
+            .retain_async(|_, v| {
+                self.removed(v);
+                i += 1;
+                false
+            })
+            .await;
+
+        debug!("cleared {i} entries");
+        i
+    }
+
     #[instrument(level = "debug", skip_all)]
     pub async fn cleanup(&self, predicate: impl Fn(&CacheKey, &CacheValue) -> bool) {
         self.retain(|k, v| {
@@ -192,14 +358,80 @@ impl Cache {
         .await
     }
 
+    /// names of all cached posts, without duplicates
+    pub async fn names(&self) -> Vec<Arc<str>> {
+        let mut names = HashSet::new();
+        self.map
+            .scan_async(|k, _| {
+                names.insert(Arc::clone(&k.name));
+            })
+            .await;
+        names.into_iter().collect()
+    }
+
     pub fn len(&self) -> usize {
         self.map.len()
     }
 
+    /// approximate size of all entries, in bytes
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// every entry, for inspecting the cache
+    pub async fn entries(&self) -> Vec<(CacheKey, CacheValue)> {
+        let mut entries = Vec::with_capacity(self.len());
+        self.map
+            .scan_async(|k, v| entries.push((k.clone(), v.clone())))
+            .await;
+        entries
+    }
+
     #[inline(always)]
     pub fn version(&self) -> u16 {
         self.version
     }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.len(),
+            bytes: self.bytes(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            version: self.version,
+            persistence: self.persistence,
+        }
+    }
+}
+
+/// stats the given files on the blocking pool, split across a few threads,
+/// and returns the mtimes of the ones that could be read
+pub async fn mtimes(files: Vec<(Arc<str>, PathBuf)>) -> collections::HashMap<Arc<str>, u64> {
+    let threads = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_size = files.len().div_ceil(threads).max(1);
+
+    let mut set = JoinSet::new();
+    for chunk in files.chunks(chunk_size) {
+        let chunk = chunk.to_vec();
+        set.spawn_blocking(move || {
+            chunk
+                .into_iter()
+                .filter_map(|(name, path)| {
+                    let mtime = std::fs::metadata(path).ok()?.modified().ok()?;
+                    Some((name, crate::systemtime_as_secs::as_secs(mtime)))
+                })
+                .collect::<Vec<_>>()
+        });
+    }
+
+    let mut mtimes = collections::HashMap::with_capacity(files.len());
+    while let Some(result) = set.join_next().await {
+        match result {
+            Ok(chunk) => mtimes.extend(chunk),
+            Err(err) => error!("failed to stat cached posts: {err}"),
+        }
+    }
+    mtimes
 }
 
 pub type ConfigAccess = Box<dyn DynAccess<CacheConfig> + Send + Sync>;
@@ -218,28 +450,82 @@ impl CacheGuard {
     }
 
     fn try_drop(&mut self) -> Result<(), eyre::Report> {
-        let config = self.config.load();
-        // write cache to file
-        let path = &*config.file;
-        let serialized = bitcode::serialize(&self.inner).context("failed to serialize cache")?;
-        let mut cache_file = std::fs::File::create(path)
-            .with_context(|| format!("failed to open cache at {}", path.display()))?;
-        let compression_level = config.compression_level;
-        if config.compress {
-            std::io::Write::write_all(
-                &mut zstd::stream::write::Encoder::new(cache_file, compression_level)?
-                    .auto_finish(),
-                &serialized,
-            )
-        } else {
-            cache_file.write_all(&serialized)
+        save(&self.inner, &self.config.load())
+    }
+
+    /// saves the cache on the blocking pool if it changed since the last
+    /// snapshot, returning whether it did
+    pub async fn snapshot(self: Arc<Self>) -> Result<bool, eyre::Report> {
+        if !self.inner.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(false);
         }
-        .context("failed to write cache to file")?;
-        info!("wrote cache to {path:?}");
-        Ok(())
+
+        tokio::task::spawn_blocking(move || {
+            let result = save(&self.inner, &self.config.load());
+            if result.is_err() {
+                self.inner.dirty.store(true, Ordering::Relaxed);
+            }
+            result.map(|()| true)
+        })
+        .await?
     }
 }
 
+/// compresses `serialized` into `file` as configured
+fn write_compressed(
+    file: std::fs::File,
+    serialized: &[u8],
+    config: &CacheConfig,
+) -> std::io::Result<std::fs::File> {
+    let level = config.compression_level;
+    match compression(config) {
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(file, level)?;
+            encoder.write_all(serialized)?;
+            encoder.finish()
+        }
+        Compression::Gzip => {
+            let level = flate2::Compression::new(level.clamp(1, 9) as u32);
+            let mut encoder = flate2::write::GzEncoder::new(file, level);
+            encoder.write_all(serialized)?;
+            encoder.finish()
+        }
+        Compression::None => {
+            let mut file = file;
+            file.write_all(serialized)?;
+            Ok(file)
+        }
+    }
+}
+
+/// writes the cache to a temporary file next to `config.file` and renames
+/// it over, so a crash mid-write leaves the previous version intact
+fn save(cache: &Cache, config: &CacheConfig) -> Result<(), eyre::Report> {
+    let path = &*config.file;
+    let serialized = bitcode::serialize(cache).context("failed to serialize cache")?;
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let tmp_file = std::fs::File::create(&tmp_path)
+            .with_context(|| format!("failed to open cache at {}", tmp_path.display()))?;
+        write_compressed(tmp_file, &serialized, config)
+            .and_then(|file| file.sync_all())
+            .context("failed to write cache to file")?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed to move cache to {}", path.display()))
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result?;
+
+    info!("wrote cache to {path:?}");
+    Ok(())
+}
+
 impl Deref for CacheGuard {
     type Target = Cache;
 
@@ -260,33 +546,228 @@ impl Drop for CacheGuard {
     }
 }
 
+/// `compress = false` predates `compression`, and still turns it off
+fn compression(config: &CacheConfig) -> Compression {
+    if config.compress {
+        config.compression
+    } else {
+        Compression::None
+    }
+}
+
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// decompresses a cache file, going by its magic bytes so it can still be
+/// read after `compression` is changed. files without any are uncompressed
+fn decompress(file: Vec<u8>, configured: Compression) -> std::io::Result<Vec<u8>> {
+    let compression = if file.starts_with(ZSTD_MAGIC) {
+        Compression::Zstd
+    } else if file.starts_with(GZIP_MAGIC) {
+        Compression::Gzip
+    } else {
+        Compression::None
+    };
+    if compression != configured {
+        info!("cache file compression is {compression:?} instead of {configured:?}");
+    }
+
+    match compression {
+        Compression::Zstd => zstd::stream::decode_all(file.as_slice()),
+        Compression::Gzip => {
+            let mut buf = Vec::with_capacity(file.len() * 4);
+            flate2::read::GzDecoder::new(file.as_slice()).read_to_end(&mut buf)?;
+            Ok(buf)
+        }
+        Compression::None => Ok(file),
+    }
+}
+
 pub(crate) async fn load_cache(config: &CacheConfig) -> Result<Cache, eyre::Report> {
     let path = &config.file;
-    let mut cache_file = tokio::fs::File::open(&path)
+    let mut file = Vec::with_capacity(4096);
+    tokio::fs::File::open(&path)
         .await
-        .context("failed to open cache file")?;
-    let serialized = if config.compress {
-        let cache_file = cache_file.into_std().await;
-        tokio::task::spawn_blocking(move || {
-            let mut buf = Vec::with_capacity(4096);
-            zstd::stream::read::Decoder::new(cache_file)?.read_to_end(&mut buf)?;
-            Ok::<_, std::io::Error>(buf)
-        })
+        .context("failed to open cache file")?
+        .read_to_end(&mut file)
+        .await
+        .context("failed to read cache file")?;
+    let configured = compression(config);
+    let serialized = tokio::task::spawn_blocking(move || decompress(file, configured))
         .await?
-        .context("failed to read cache file")?
-    } else {
-        let mut buf = Vec::with_capacity(4096);
-        cache_file
-            .read_to_end(&mut buf)
-            .await
-            .context("failed to read cache file")?;
-        buf
-    };
+        .context("failed to decompress cache file")?;
 
     let mut cache: Cache =
         bitcode::deserialize(serialized.as_slice()).context("failed to parse cache")?;
 
     cache.ttl = config.ttl;
+    cache.max_entries = config.max_entries;
+    cache.max_bytes = config.max_bytes;
+    cache.persistence = config.persistence;
+
+    let mut bytes = 0;
+    cache.map.scan_async(|_, v| bytes += v.size()).await;
+    *cache.bytes.get_mut() = bytes;
 
     Ok(cache)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::post::tests::metadata;
+
+    fn cache(max_entries: Option<usize>, max_bytes: Option<usize>) -> Cache {
+        Cache::new(&CacheConfig {
+            max_entries: max_entries.and_then(NonZeroUsize::new),
+            max_bytes: max_bytes.and_then(NonZeroUsize::new),
+            persistence: false,
+            ..Default::default()
+        })
+    }
+
+    async fn insert(cache: &Cache, name: &str, body: &str) {
+        cache
+            .insert(name.into(), metadata(name, &[]), 0, body.into(), 0)
+            .await;
+        // timestamps are in milliseconds, keep them apart
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+
+    async fn names(cache: &Cache) -> Vec<Arc<str>> {
+        let mut names = cache.names().await;
+        names.sort_unstable();
+        names
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used() {
+        let cache = cache(Some(2), None);
+        insert(&cache, "a", "").await;
+        insert(&cache, "b", "").await;
+        // a is now more recent than b
+        assert!(cache.lookup("a".into(), 0, 0).await.is_some());
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        insert(&cache, "c", "").await;
+
+        assert_eq!(names(&cache).await, ["a".into(), "c".into()]);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn evicts_oldest_over_max_bytes() {
+        let body = "x".repeat(1000);
+        let entry = CacheValue {
+            meta: metadata("a", &[]),
+            body: body.as_str().into(),
+            mtime: 0,
+            cached_at: 0,
+            last_accessed: 0,
+        }
+        .size();
+        let cache = cache(None, Some(entry * 2));
+
+        insert(&cache, "a", &body).await;
+        insert(&cache, "b", &body).await;
+        assert_eq!(cache.bytes(), entry * 2);
+
+        insert(&cache, "c", &body).await;
+        assert_eq!(names(&cache).await, ["b".into(), "c".into()]);
+        assert_eq!(cache.bytes(), entry * 2);
+
+        // one entry bigger than the limit doesn't stay either
+        insert(&cache, "d", &body.repeat(3)).await;
+        assert!(cache.bytes() <= entry * 2);
+        assert!(cache.lookup("c".into(), 0, 0).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn tracks_bytes() {
+        let cache = cache(None, None);
+        insert(&cache, "a", "hello").await;
+        insert(&cache, "a", "hello, world").await;
+        let size = cache.bytes();
+        assert_eq!(cache.len(), 1);
+        assert!(size > "hello, world".len());
+
+        cache.remove("a".into(), 0).await;
+        assert_eq!(cache.bytes(), 0);
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("bingus-blog-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_config(file: &std::path::Path, compression: Compression) -> CacheConfig {
+        CacheConfig {
+            file: file.into(),
+            compression,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn loads_after_compression_changes() {
+        let dir = temp_dir("cache-compression");
+        let file = dir.join("cache");
+
+        for (written, configured) in [
+            (Compression::Gzip, Compression::Zstd),
+            (Compression::Zstd, Compression::Gzip),
+            (Compression::None, Compression::Zstd),
+        ] {
+            let cache = cache(None, None);
+            insert(&cache, "a", "hello").await;
+            save(&cache, &file_config(&file, written)).unwrap();
+
+            let loaded = load_cache(&file_config(&file, configured)).await.unwrap();
+            assert_eq!(names(&loaded).await, ["a".into()], "{written:?}");
+            assert_eq!(loaded.bytes(), cache.bytes());
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_save_leaves_target() {
+        let dir = temp_dir("cache-save");
+        // renaming a file over a directory fails
+        let file = dir.join("cache");
+        std::fs::create_dir(&file).unwrap();
+        std::fs::write(file.join("keep"), "kept").unwrap();
+
+        let cache = cache(None, None);
+        insert(&cache, "a", "hello").await;
+        assert!(save(&cache, &file_config(&file, Compression::Zstd)).is_err());
+
+        assert_eq!(std::fs::read_to_string(file.join("keep")).unwrap(), "kept");
+        assert!(!dir.join("cache.tmp").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn snapshot_skips_unchanged() {
+        let dir = temp_dir("cache-snapshot");
+        let config = file_config(&dir.join("cache"), Compression::Zstd);
+        let guard = Arc::new(CacheGuard::new(
+            cache(None, None),
+            Box::new(arc_swap::ArcSwap::from_pointee(config)),
+        ));
+
+        assert!(!guard.clone().snapshot().await.unwrap());
+        assert!(!dir.join("cache").exists());
+
+        insert(&guard, "a", "hello").await;
+        assert!(guard.clone().snapshot().await.unwrap());
+        assert!(dir.join("cache").exists());
+        assert!(!guard.clone().snapshot().await.unwrap());
+
+        drop(guard);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
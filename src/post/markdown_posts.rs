@@ -1,39 +1,49 @@
 use std::collections::BTreeSet;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 use std::time::SystemTime;
 
 use arc_swap::access::Access;
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
-use axum::http::HeaderValue;
+use axum::http::{HeaderMap, HeaderValue};
 use chrono::{DateTime, Utc};
 use color_eyre::eyre::{self, Context};
-use comrak::plugins::syntect::SyntectAdapter;
 use fronma::parser::{parse, ParsedData};
+use futures::stream;
+use futures::{FutureExt, StreamExt};
 use indexmap::IndexMap;
 use serde::Deserialize;
 use serde_value::Value;
 use tokio::fs;
-use tokio::io::AsyncReadExt;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
 use tracing::{error, info, instrument};
 
 use crate::config::MarkdownConfig;
-use crate::markdown_render::{build_syntect, render};
+use crate::markdown_render::{render, Highlighters};
+use crate::path::{relative_name, BasePathAccess};
 use crate::systemtime_as_secs::as_secs;
+use crate::text::{first_paragraph, reading_time, truncate_words, word_count};
 
-use super::cache::{CacheGuard, CacheKey, CacheValue};
+use super::cache::{mtimes, CacheGuard, CacheKey, CacheStats, CacheValue};
+use super::slugs::{PostLinks, SlugConflictAccess, SlugIndex};
 use super::{
-    ApplyFilters, Filter, PostError, PostManager, PostMetadata, RenderStats, ReturnedPost,
+    ApplyFilters, PostError, PostFilter, PostManager, PostMetadata, RenderStats, ReturnedPost,
+    StyleAccess,
 };
 
 #[derive(Deserialize)]
 struct FrontMatter {
+    pub slug: Option<Arc<str>>,
+    #[serde(default)]
+    pub aliases: Vec<Arc<str>>,
     pub title: Arc<str>,
-    pub description: Arc<str>,
+    pub description: Option<Arc<str>>,
     pub author: Arc<str>,
     pub icon: Option<Arc<str>>,
     pub icon_alt: Option<Arc<str>>,
@@ -43,6 +53,46 @@ struct FrontMatter {
     pub modified_at: Option<DateTime<Utc>>,
     #[serde(default)]
     pub tags: BTreeSet<Arc<str>>,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub pinned: bool,
+    pub syntect_theme: Option<Arc<str>>,
+    pub series: Option<Arc<str>>,
+    pub series_index: Option<u32>,
+    pub publish_at: Option<DateTime<Utc>>,
+    pub unpublish_at: Option<DateTime<Utc>>,
+    pub template: Option<Arc<str>>,
+    pub raw: Option<bool>,
+}
+
+/// the part of the front matter deciding if the raw file is served
+#[derive(Deserialize)]
+struct RawFrontMatter {
+    raw: Option<bool>,
+}
+
+/// reads just the front matter off the start of a post, so the rest of it
+/// doesn't have to be in memory
+async fn read_front_matter(file: &mut File) -> io::Result<Option<String>> {
+    let mut reader = BufReader::new(file);
+    let mut front_matter = String::new();
+    loop {
+        let start = front_matter.len();
+        match reader.read_line(&mut front_matter).await {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let line = front_matter[start..].trim_end();
+        if line == "---" && start > 0 {
+            return Ok(Some(front_matter));
+        }
+        if start == 0 && line != "---" {
+            return Ok(None);
+        }
+    }
 }
 
 impl FrontMatter {
@@ -54,8 +104,10 @@ impl FrontMatter {
     ) -> PostMetadata {
         PostMetadata {
             name,
+            slug: self.slug,
+            aliases: self.aliases,
             title: self.title,
-            description: self.description,
+            description: self.description.unwrap_or_else(|| Arc::from("")),
             author: self.author,
             icon: self.icon,
             icon_alt: self.icon_alt,
@@ -63,6 +115,43 @@ impl FrontMatter {
             written_at: self.written_at.or_else(|| created.map(|t| t.into())),
             modified_at: self.modified_at.or_else(|| modified.map(|t| t.into())),
             tags: self.tags.into_iter().collect(),
+            draft: self.draft,
+            pinned: self.pinned,
+            series: self.series,
+            series_index: self.series_index,
+            publish_at: self.publish_at,
+            unpublish_at: self.unpublish_at,
+            template: self.template,
+            raw_access: self.raw,
+            excerpt_html: None,
+            state: Default::default(),
+            reading_time_minutes: None,
+            word_count: None,
+            body_bytes: 0,
+            content_hash: Arc::from(""),
+        }
+    }
+}
+
+/// everything derived from the render options, rebuilt when they change
+struct RenderState {
+    /// hash of the options this was built from
+    config_hash: u64,
+    /// hash of everything that affects the rendered output, for the cache
+    render_hash: u64,
+    highlighters: Arc<Highlighters>,
+}
+
+impl RenderState {
+    fn new(config_hash: u64, highlighters: Arc<Highlighters>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        config_hash.hash(&mut hasher);
+        // so a theme posts asked for showing up invalidates their fallbacks
+        highlighters.theme_names().hash(&mut hasher);
+        Self {
+            config_hash,
+            render_hash: hasher.finish(),
+            highlighters,
         }
     }
 }
@@ -70,8 +159,11 @@ impl FrontMatter {
 pub struct MarkdownPosts<A> {
     cache: Option<Arc<CacheGuard>>,
     config: A,
-    render_hash: u64,
-    syntect: SyntectAdapter,
+    slug_conflict: SlugConflictAccess,
+    style: StyleAccess,
+    base_path: BasePathAccess,
+    render: ArcSwap<RenderState>,
+    slug_index: SlugIndex,
 }
 
 impl<A> MarkdownPosts<A>
@@ -80,22 +172,66 @@ where
     A: Sync,
     A::Guard: Send,
 {
-    pub async fn new(config: A, cache: Option<Arc<CacheGuard>>) -> eyre::Result<Self> {
-        let syntect = build_syntect(&config.load().render)
+    pub async fn new(
+        config: A,
+        cache: Option<Arc<CacheGuard>>,
+        slug_conflict: SlugConflictAccess,
+        style: StyleAccess,
+        base_path: BasePathAccess,
+    ) -> eyre::Result<Self> {
+        let highlighters = Highlighters::new(&config.load().render)
             .context("failed to create syntax highlighting engine")?;
-
-        let mut hasher = DefaultHasher::new();
-        config.load().render.hash(&mut hasher);
-        let render_hash = hasher.finish();
+        let render = RenderState::new(
+            Self::config_hash(&config, &base_path),
+            Arc::new(highlighters),
+        );
 
         Ok(Self {
             cache,
             config,
-            render_hash,
-            syntect,
+            slug_conflict,
+            style,
+            base_path,
+            render: ArcSwap::from_pointee(render),
+            slug_index: Default::default(),
         })
     }
 
+    fn config_hash(config: &A, base_path: &BasePathAccess) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let config = config.load();
+        config.render.hash(&mut hasher);
+        config.more_marker.hash(&mut hasher);
+        base_path.load().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// the current render state, rebuilt first if the render options were
+    /// changed by a config reload
+    fn render_state(&self) -> Arc<RenderState> {
+        let config_hash = Self::config_hash(&self.config, &self.base_path);
+        let current = self.render.load_full();
+        if current.config_hash == config_hash {
+            return current;
+        }
+
+        let highlighters = match Highlighters::new(&self.config.load().render) {
+            Ok(highlighters) => {
+                info!("render options changed, rebuilt syntax highlighting engine");
+                Arc::new(highlighters)
+            }
+            Err(err) => {
+                error!(
+                    "failed to rebuild syntax highlighting engine, keeping the old one: {err:?}"
+                );
+                Arc::clone(&current.highlighters)
+            }
+        };
+        let render = Arc::new(RenderState::new(config_hash, highlighters));
+        self.render.store(Arc::clone(&render));
+        render
+    }
+
     async fn parse_and_render(
         &self,
         name: Arc<str>,
@@ -117,13 +253,57 @@ where
         file.read_to_string(&mut content).await?;
 
         let ParsedData { headers, body } = parse::<FrontMatter>(&content)?;
-        let metadata = headers.into_full(name.to_owned(), created, Some(modified));
+        let auto_description = headers.description.is_none();
+        // the theme is in the front matter, so changing it changes the mtime
+        // and invalidates the cached render
+        let render_state = self.render_state();
+        let syntect = render_state
+            .highlighters
+            .get(headers.syntect_theme.as_deref());
+        let mut metadata = headers.into_full(name.to_owned(), created, Some(modified));
         let parsing = parsing_start.elapsed();
 
         let before_render = Instant::now();
-        let post = render(body, &self.config.load().render, Some(&self.syntect)).into();
+        // comrak is cpu-bound, keep it off the async workers
+        let body = body.to_owned();
+        let (config, more_marker) = {
+            let config = self.config.load();
+            (config.render.clone(), config.more_marker.clone())
+        };
+        let base_path = self.base_path.load().clone();
+        let (post, excerpt) = tokio::task::spawn_blocking(move || {
+            // an empty marker turns excerpts off
+            match body.find(&*more_marker).filter(|_| !more_marker.is_empty()) {
+                Some(index) => (
+                    render(
+                        &body.replacen(&*more_marker, "", 1),
+                        &config,
+                        Some(&*syntect),
+                        &base_path,
+                    ),
+                    Some(render(&body[..index], &config, Some(&*syntect), &base_path)),
+                ),
+                None => (render(&body, &config, Some(&*syntect), &base_path), None),
+            }
+        })
+        .await
+        .map_err(|err| PostError::RenderError(err.to_string()))?;
+        let post: Arc<str> = post.into();
+        metadata.excerpt_html = excerpt.map(Into::into);
         let rendering = before_render.elapsed();
 
+        let words = word_count(&post);
+        metadata.word_count = Some(words);
+        {
+            let style = self.style.load();
+            metadata.reading_time_minutes = reading_time(words, style.words_per_minute);
+            if auto_description && let Some(paragraph) = first_paragraph(&post) {
+                metadata.description =
+                    truncate_words(&paragraph, style.auto_description_length).into();
+            }
+        }
+        metadata.describe_body(&post);
+
         if let Some(cache) = &self.cache {
             cache
                 .insert(
@@ -131,7 +311,7 @@ where
                     metadata.clone(),
                     as_secs(modified),
                     Arc::clone(&post),
-                    self.render_hash,
+                    render_state.render_hash,
                 )
                 .await;
         }
@@ -139,123 +319,64 @@ where
         Ok((metadata, post, (parsing, rendering)))
     }
 
-    fn is_raw(name: &str) -> bool {
-        name.ends_with(".md")
-    }
-
-    fn as_raw(name: &str) -> Option<String> {
-        let mut buf = String::with_capacity(name.len() + 3);
-        buf += name;
-        buf += ".md";
-
-        Some(buf)
-    }
-}
-
-#[async_trait]
-impl<A> PostManager for MarkdownPosts<A>
-where
-    A: Access<MarkdownConfig>,
-    A: Sync,
-    A::Guard: Send,
-{
-    async fn get_all_posts(
-        &self,
-        filters: &[Filter<'_>],
-        query: &IndexMap<String, Value>,
-    ) -> Result<Vec<(PostMetadata, Arc<str>, RenderStats)>, PostError> {
+    /// every post under the root, by name
+    async fn scan(&self) -> Result<Vec<(Arc<str>, PathBuf)>, PostError> {
         let mut posts = Vec::new();
+        let root = self.config.load().root.clone();
 
-        let mut read_dir = fs::read_dir(&self.config.load().root).await?;
-        while let Some(entry) = read_dir.next_entry().await? {
-            if let Err(err) = async {
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let mut read_dir = fs::read_dir(&dir).await?;
+            while let Some(entry) = read_dir.next_entry().await? {
                 let path = entry.path();
-                let stat = fs::metadata(&path).await?;
+                // symlinked directories are not followed, to avoid loops
+                if entry.file_type().await.is_ok_and(|ty| ty.is_dir()) {
+                    dirs.push(path);
+                    continue;
+                }
+                if path.extension().is_none_or(|ext| ext != "md") {
+                    continue;
+                }
 
-                if stat.is_file() && path.extension().is_some_and(|ext| ext == "md") {
-                    let name = path
-                        .clone()
-                        .file_stem()
-                        .unwrap()
-                        .to_string_lossy()
-                        .to_string()
-                        .into();
-
-                    let post = self.get_post(Arc::clone(&name), query).await?;
-                    if let ReturnedPost::Rendered {
-                        meta, body, perf, ..
-                    } = post
-                        && meta.apply_filters(filters)
-                    {
-                        posts.push((meta, body, perf));
+                match fs::metadata(&path).await {
+                    Ok(stat) if stat.is_file() => {}
+                    Ok(_) => continue,
+                    Err(err) => {
+                        error!("error while getting post {path:?}: {err}");
+                        continue;
                     }
                 }
 
-                color_eyre::eyre::Ok(())
+                let Some(name) = relative_name(&root, &path) else {
+                    continue;
+                };
+                posts.push((name.into(), path));
             }
-            .await
-            {
-                error!("error while getting post: {err}");
-                continue;
-            };
         }
 
         Ok(posts)
     }
 
-    async fn get_all_post_metadata(
-        &self,
-        filters: &[Filter<'_>],
-        _query: &IndexMap<String, Value>,
-    ) -> Result<Vec<PostMetadata>, PostError> {
-        let mut posts = Vec::new();
-
-        let mut read_dir = fs::read_dir(&self.config.load().root).await?;
-        while let Some(entry) = read_dir.next_entry().await? {
-            if let Err(err) = async {
-                let path = entry.path();
-                let stat = fs::metadata(&path).await?;
-
-                if stat.is_file() && path.extension().is_some_and(|ext| ext == "md") {
-                    let mtime = as_secs(stat.modified()?);
-                    let name: Arc<str> =
-                        String::from(path.file_stem().unwrap().to_string_lossy()).into();
-
-                    if let Some(cache) = &self.cache
-                        && let Some(hit) = cache
-                            .lookup_metadata(name.clone(), mtime, self.render_hash)
-                            .await
-                        && hit.apply_filters(filters)
-                    {
-                        posts.push(hit);
-                    } else {
-                        let (metadata, ..) = self.parse_and_render(name, path).await?;
-                        if metadata.apply_filters(filters) {
-                            posts.push(metadata);
-                        }
-                    }
-                }
+    fn is_raw(name: &str) -> bool {
+        name.ends_with(".md")
+    }
 
-                color_eyre::eyre::Ok(())
-            }
-            .await
-            {
-                error!("error while getting post metadata: {err}");
-                continue;
-            };
-        }
+    fn as_raw(name: &str) -> Option<String> {
+        let mut buf = String::with_capacity(name.len() + 3);
+        buf += name;
+        buf += ".md";
 
-        Ok(posts)
+        Some(buf)
     }
 
     #[instrument(level = "info", skip(self))]
-    async fn get_post(
+    async fn get_post_by_name(
         &self,
         name: Arc<str>,
         _query: &IndexMap<String, Value>,
     ) -> Result<ReturnedPost, PostError> {
         let config = self.config.load();
-        let post = if config.raw_access && Self::is_raw(&name) {
+        let post = if Self::is_raw(&name) {
             let path = config.root.join(&*name);
 
             let mut file = match tokio::fs::OpenOptions::new().read(true).open(&path).await {
@@ -268,14 +389,24 @@ where
                 }
             };
 
-            let mut buffer = Vec::with_capacity(4096);
-
-            file.read_to_end(&mut buffer).await?;
-
-            ReturnedPost::Raw {
-                buffer,
-                content_type: HeaderValue::from_static("text/plain"),
+            // a broken front matter is left for the rendered post to report
+            let raw_access = read_front_matter(&mut file)
+                .await?
+                .and_then(|front_matter| {
+                    parse::<RawFrontMatter>(&front_matter)
+                        .ok()
+                        .and_then(|parsed| parsed.headers.raw)
+                })
+                .unwrap_or(config.raw_access);
+            if !raw_access {
+                return Err(PostError::NotFound(name));
             }
+            file.rewind().await?;
+
+            // validated when the config was loaded
+            let content_type = HeaderValue::from_str(&config.raw_content_type)
+                .unwrap_or_else(|_| HeaderValue::from_static("text/plain; charset=utf-8"));
+            ReturnedPost::raw_file(file, content_type).await?
         } else {
             let start = Instant::now();
             let raw_name = Self::as_raw(&name).unwrap_or_else(|| unreachable!());
@@ -292,9 +423,10 @@ where
             };
             let mtime = as_secs(stat.modified()?);
 
-            let (meta, body, perf) = if let Some(cache) = &self.cache
+            let render_hash = self.render_state().render_hash;
+            let (mut meta, body, perf) = if let Some(cache) = &self.cache
                 && let Some(CacheValue { meta, body, .. }) =
-                    cache.lookup(name.clone(), mtime, self.render_hash).await
+                    cache.lookup(name.clone(), mtime, render_hash).await
             {
                 (meta, body, RenderStats::Cached(start.elapsed()))
             } else {
@@ -310,11 +442,15 @@ where
                 )
             };
 
+            meta.refresh_state();
+            let raw_access = meta.raw_access.unwrap_or(config.raw_access);
+
             ReturnedPost::Rendered {
                 meta,
                 body,
                 perf,
-                raw_name: config.raw_access.then_some(raw_name),
+                raw_name: raw_access.then_some(raw_name),
+                render_hash,
             }
         };
 
@@ -324,32 +460,245 @@ where
 
         Ok(post)
     }
+}
+
+#[async_trait]
+impl<A> PostManager for MarkdownPosts<A>
+where
+    A: Access<MarkdownConfig>,
+    A: Sync,
+    A::Guard: Send,
+{
+    async fn get_all_posts(
+        &self,
+        filters: &[Box<dyn PostFilter + '_>],
+        query: &IndexMap<String, Value>,
+    ) -> Result<Vec<(PostMetadata, Arc<str>, RenderStats)>, PostError> {
+        let concurrency = self.config.load().concurrency.get();
+        let mut posts = Vec::new();
+        let mut links = Vec::new();
+
+        let mut set = stream::iter(self.scan().await?)
+            .map(|(name, _)| {
+                self.get_post_by_name(Arc::clone(&name), query)
+                    .map(|v| (name, v))
+            })
+            .buffer_unordered(concurrency);
+
+        while let Some((name, result)) = set.next().await {
+            match result {
+                Ok(ReturnedPost::Rendered {
+                    meta, body, perf, ..
+                }) => {
+                    links.push(PostLinks::from(&meta));
+                    if meta.apply_filters(filters) {
+                        posts.push((meta, body, perf));
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => error!("error while getting post {name:?}: {err}"),
+            }
+        }
+
+        self.slug_index.rebuild(links, *self.slug_conflict.load())?;
+        for (meta, ..) in &mut posts {
+            self.slug_index.verify(meta);
+        }
+
+        Ok(posts)
+    }
+
+    async fn get_all_post_metadata(
+        &self,
+        filters: &[Box<dyn PostFilter + '_>],
+        _query: &IndexMap<String, Value>,
+    ) -> Result<Vec<PostMetadata>, PostError> {
+        let mut posts = Vec::new();
+        let mut links = Vec::new();
+
+        let render_hash = self.render_state().render_hash;
+        for (name, path) in self.scan().await? {
+            if let Err(err) = async {
+                let stat = fs::metadata(&path).await?;
+                let mtime = as_secs(stat.modified()?);
+
+                let mut metadata = if let Some(cache) = &self.cache
+                    && let Some(hit) = cache
+                        .lookup_metadata(name.clone(), mtime, render_hash)
+                        .await
+                {
+                    hit
+                } else {
+                    self.parse_and_render(name.clone(), path).await?.0
+                };
+
+                links.push(PostLinks::from(&metadata));
+                if metadata.apply_filters(filters) {
+                    metadata.refresh_state();
+                    posts.push(metadata);
+                }
+
+                color_eyre::eyre::Ok(())
+            }
+            .await
+            {
+                error!("error while getting post metadata for {name:?}: {err}");
+                continue;
+            };
+        }
+
+        self.slug_index.rebuild(links, *self.slug_conflict.load())?;
+        for meta in &mut posts {
+            self.slug_index.verify(meta);
+        }
+
+        Ok(posts)
+    }
+
+    async fn get_post(
+        &self,
+        name: Arc<str>,
+        query: &IndexMap<String, Value>,
+        _headers: &HeaderMap,
+    ) -> Result<ReturnedPost, PostError> {
+        let mut result = match self.get_post_by_name(Arc::clone(&name), query).await {
+            // filenames take precedence over slugs
+            Err(PostError::NotFound(_)) => {
+                let mut resolved = self.slug_index.resolve(&name);
+                // the slug may be new, or from a new post
+                if resolved.is_none() && self.slug_index.needs_build() {
+                    let _ = self.get_all_post_metadata(&[], query).await;
+                    resolved = self.slug_index.resolve(&name);
+                }
+                match resolved {
+                    Some(name) => self.get_post_by_name(name, query).await,
+                    None => Err(PostError::NotFound(name)),
+                }
+            }
+            result => result,
+        };
+
+        if let Ok(ReturnedPost::Rendered { meta, .. }) = &mut result {
+            self.slug_index.verify(meta);
+        }
+
+        result
+    }
+
+    async fn resolve_alias(&self, name: &str) -> Option<Arc<str>> {
+        let resolved = self.slug_index.resolve_alias(name);
+        if resolved.is_none() && self.slug_index.needs_build() {
+            let _ = self.get_all_post_metadata(&[], &IndexMap::new()).await;
+            return self.slug_index.resolve_alias(name);
+        }
+        resolved
+    }
+
+    async fn root_ok(&self) -> bool {
+        let root = self.config.load().root.clone();
+        fs::read_dir(root).await.is_ok()
+    }
 
     async fn cleanup(&self) {
         if let Some(cache) = &self.cache {
+            let render_hash = self.render_state().render_hash;
+            let root = self.config.load().root.clone();
+            let files = cache
+                .names()
+                .await
+                .into_iter()
+                .map(|name| {
+                    let path = root.join(Self::as_raw(&name).unwrap_or_else(|| unreachable!()));
+                    (name, path)
+                })
+                .collect();
+            let mtimes = mtimes(files).await;
+
             cache
                 .cleanup(|CacheKey { name, extra }, value| {
                     // nuke entries with different render options
-                    if self.render_hash != *extra {
+                    if render_hash != *extra {
                         return false;
                     }
 
-                    let mtime = std::fs::metadata(
-                        self.config
-                            .load()
-                            .root
-                            .join(Self::as_raw(name).unwrap_or_else(|| unreachable!())),
-                    )
-                    .ok()
-                    .and_then(|metadata| metadata.modified().ok())
-                    .map(as_secs);
-
-                    match mtime {
-                        Some(mtime) => mtime <= value.mtime,
+                    match mtimes.get(name) {
+                        Some(&mtime) => mtime <= value.mtime,
                         None => false,
                     }
                 })
                 .await
         }
     }
+
+    async fn clear_cache(&self) -> usize {
+        match &self.cache {
+            Some(cache) => cache.clear().await,
+            None => 0,
+        }
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use arc_swap::access::Map;
+
+    use super::*;
+    use crate::config::{Config, SyntectConfig};
+
+    /// rendering happens on the blocking pool, so one big post doesn't stop
+    /// other requests from being served in the meantime
+    #[tokio::test(flavor = "current_thread")]
+    async fn render_does_not_block_the_runtime() {
+        let root = std::env::temp_dir().join(format!("bingus-blog-render-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let body = "some *very* long paragraph with `code` in it\n\n".repeat(5_000);
+        std::fs::write(
+            root.join("big.md"),
+            format!("---\ntitle: big\nauthor: bingus\n---\n{body}"),
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        config.engine.markdown.root = root.clone().into();
+        config.engine.markdown.render.syntect = SyntectConfig {
+            load_defaults: false,
+            themes_dir: None,
+            theme: None,
+        };
+        let swapper = Arc::new(ArcSwap::from_pointee(config));
+        let posts = MarkdownPosts::new(
+            Map::new(swapper.clone(), |c: &Config| &c.engine.markdown),
+            None,
+            Box::new(Map::new(swapper.clone(), |c: &Config| {
+                &c.engine.slug_conflict
+            })),
+            Box::new(Map::new(swapper.clone(), |c: &Config| &c.style)),
+            Box::new(Map::new(swapper.clone(), |c: &Config| &c.http.base_path)),
+        )
+        .await
+        .unwrap();
+
+        // with a single thread, this only runs if the render yields it
+        let ticks = Cell::new(0u128);
+        let ticker = async {
+            loop {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                ticks.set(ticks.get() + 1);
+            }
+        };
+        let (_, _, (_, rendering)) = tokio::select! {
+            result = posts.parse_and_render("big".into(), root.join("big.md")) => result.unwrap(),
+            _ = ticker => unreachable!(),
+        };
+        std::fs::remove_dir_all(&root).unwrap();
+
+        // timers are too coarse under load to count on more than one tick
+        assert!(ticks.get() > 0, "no ticks in {rendering:?}");
+    }
 }
@@ -1,25 +1,53 @@
 pub mod blag;
 pub mod cache;
 pub mod markdown_posts;
+pub mod slugs;
 
+use std::fmt::Write;
 use std::sync::Arc;
 use std::time::Duration;
 
+use arc_swap::access::DynAccess;
 use async_trait::async_trait;
-use axum::http::HeaderValue;
+use axum::body::Body;
+use axum::http::{HeaderMap, HeaderValue};
 use chrono::{DateTime, Utc};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use serde_value::Value;
+use sha2::{Digest, Sha256};
+use tokio::fs::File;
+use tokio_util::io::ReaderStream;
 
+use crate::config::{Sort, StyleConfig};
 use crate::error::PostError;
+use crate::post::cache::CacheStats;
 pub use blag::Blag;
 pub use markdown_posts::MarkdownPosts;
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishState {
+    Draft,
+    Scheduled,
+    #[default]
+    Published,
+    Unpublished,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
 // TODO: replace String with Arc<str>
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PostMetadata {
     pub name: Arc<str>,
+    pub slug: Option<Arc<str>>,
+    pub aliases: Vec<Arc<str>>,
     pub title: Arc<str>,
     pub description: Arc<str>,
     pub author: Arc<str>,
@@ -29,6 +57,66 @@ pub struct PostMetadata {
     pub written_at: Option<DateTime<Utc>>,
     pub modified_at: Option<DateTime<Utc>>,
     pub tags: Vec<Arc<str>>,
+    pub draft: bool,
+    pub pinned: bool,
+    pub series: Option<Arc<str>>,
+    pub series_index: Option<u32>,
+    pub publish_at: Option<DateTime<Utc>>,
+    pub unpublish_at: Option<DateTime<Utc>>,
+    pub template: Option<Arc<str>>,
+    /// overrides the engine's `raw_access` for this post
+    pub raw_access: Option<bool>,
+    /// the post up to the `more_marker`, shown in listings instead of the
+    /// description
+    pub excerpt_html: Option<Arc<str>>,
+    pub state: PublishState,
+    // not skipped when None, bitcode (the cache) needs every field to be present
+    pub reading_time_minutes: Option<u32>,
+    pub word_count: Option<u32>,
+    pub body_bytes: usize,
+    pub content_hash: Arc<str>,
+}
+
+impl PostMetadata {
+    /// what the post is linked as, its `slug` if it has one
+    pub fn url_name(&self) -> &str {
+        self.slug.as_deref().unwrap_or(&self.name)
+    }
+
+    /// works out the publish state of the post at the given time
+    pub fn state_at(&self, now: DateTime<Utc>) -> PublishState {
+        if self.draft {
+            PublishState::Draft
+        } else if self.unpublish_at.is_some_and(|date| date <= now) {
+            PublishState::Unpublished
+        } else if self
+            .publish_at
+            .or(self.written_at)
+            .is_some_and(|date| date > now)
+        {
+            PublishState::Scheduled
+        } else {
+            PublishState::Published
+        }
+    }
+
+    /// updates [`PostMetadata::state`], which goes stale in the cache as time
+    /// passes
+    pub fn refresh_state(&mut self) {
+        self.state = self.state_at(Utc::now());
+    }
+
+    /// fills in [`PostMetadata::body_bytes`] and [`PostMetadata::content_hash`]
+    pub fn describe_body(&mut self, body: &str) {
+        let digest = Sha256::digest(body.as_bytes());
+        let mut hash = String::with_capacity(16);
+        for byte in &digest[..8] {
+            let _ = write!(hash, "{byte:02x}");
+        }
+
+        self.body_bytes = body.len();
+        self.content_hash = hash.into();
+    }
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -49,42 +137,156 @@ pub enum RenderStats {
 }
 
 #[allow(clippy::large_enum_variant)] // Raw will be returned very rarely
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum ReturnedPost {
     Rendered {
         meta: PostMetadata,
         body: Arc<str>,
         perf: RenderStats,
         raw_name: Option<String>,
+        /// hash of the options the body was rendered with
+        render_hash: u64,
     },
     Raw {
-        buffer: Vec<u8>,
+        body: Body,
+        /// unknown for generated output
+        length: Option<u64>,
         content_type: HeaderValue,
+        modified_at: Option<DateTime<Utc>>,
     },
 }
 
+impl ReturnedPost {
+    /// streams the rest of `file` instead of reading it into memory
+    pub async fn raw_file(file: File, content_type: HeaderValue) -> Result<Self, PostError> {
+        let stat = file.metadata().await?;
+        Ok(Self::Raw {
+            body: Body::from_stream(ReaderStream::new(file)),
+            length: Some(stat.len()),
+            content_type,
+            modified_at: stat.modified().ok().map(Into::into),
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    /// posts with any of the tags
+    #[default]
+    Any,
+    /// posts with every one of the tags
+    All,
+}
+
 pub enum Filter<'a> {
     Tags(&'a [&'a str]),
+    AllTags(&'a [&'a str]),
+    /// posts with none of the tags
+    ExcludeTags(&'a [&'a str]),
+    /// posts by the given author, case-insensitively
+    Author(&'a str),
+    /// posts written between the given dates, inclusive. posts without a
+    /// `written_at` never match
+    DateRange {
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    },
+    /// posts in the given series
+    Series(&'a str),
+    /// only match posts that are published at the given time
+    Published(DateTime<Utc>),
 }
 
-impl Filter<'_> {
-    pub fn apply(&self, meta: &PostMetadata) -> bool {
+/// criteria for matching posts, implement this to filter by anything
+/// [`Filter`] doesn't cover
+pub trait PostFilter: Send + Sync {
+    fn matches(&self, meta: &PostMetadata) -> bool;
+}
+
+impl PostFilter for Filter<'_> {
+    fn matches(&self, meta: &PostMetadata) -> bool {
         match self {
             Filter::Tags(tags) => tags
                 .iter()
                 .any(|tag| meta.tags.iter().any(|meta_tag| &**meta_tag == *tag)),
+            Filter::AllTags(tags) => tags
+                .iter()
+                .all(|tag| meta.tags.iter().any(|meta_tag| &**meta_tag == *tag)),
+            Filter::ExcludeTags(tags) => !tags
+                .iter()
+                .any(|tag| meta.tags.iter().any(|meta_tag| &**meta_tag == *tag)),
+            Filter::Author(author) => meta.author.to_lowercase() == author.to_lowercase(),
+            Filter::DateRange { from, to } => meta.written_at.is_some_and(|date| {
+                from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+            }),
+            Filter::Series(series) => meta.series.as_deref() == Some(*series),
+            Filter::Published(now) => meta.state_at(*now) == PublishState::Published,
         }
     }
 }
 
+pub type StyleAccess = Box<dyn DynAccess<StyleConfig> + Send + Sync>;
+
+/// what a listing was asked to be narrowed down to
+#[derive(Default)]
+pub struct ListingQuery<'a> {
+    pub tags: Vec<&'a str>,
+    pub tag_match: TagMatch,
+    pub exclude: Vec<&'a str>,
+    pub author: Option<&'a str>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub series: Option<&'a str>,
+}
+
+/// filters for posts that should show up in listings
+pub fn listing_filters<'a>(listing: &'a ListingQuery<'a>) -> Vec<Box<dyn PostFilter + 'a>> {
+    let mut filters: Vec<Box<dyn PostFilter + 'a>> = vec![Box::new(Filter::Published(Utc::now()))];
+    if !listing.tags.is_empty() {
+        filters.push(Box::new(match listing.tag_match {
+            TagMatch::Any => Filter::Tags(&listing.tags),
+            TagMatch::All => Filter::AllTags(&listing.tags),
+        }));
+    }
+    if !listing.exclude.is_empty() {
+        filters.push(Box::new(Filter::ExcludeTags(&listing.exclude)));
+    }
+    if let Some(author) = listing.author {
+        filters.push(Box::new(Filter::Author(author)));
+    }
+    if listing.from.is_some() || listing.to.is_some() {
+        filters.push(Box::new(Filter::DateRange {
+            from: listing.from,
+            to: listing.to,
+        }));
+    }
+    if let Some(series) = listing.series {
+        filters.push(Box::new(Filter::Series(series)));
+    }
+    filters
+}
+
+/// sorts posts of a series into reading order: by `series_index`, with
+/// unindexed posts after the indexed ones, oldest first
+pub fn sort_series(posts: &mut [PostMetadata]) {
+    posts.sort_by(|a, b| {
+        (a.series_index.is_none(), a.series_index, a.written_at).cmp(&(
+            b.series_index.is_none(),
+            b.series_index,
+            b.written_at,
+        ))
+    });
+}
+
 pub trait ApplyFilters {
-    fn apply_filters(&self, filters: &[Filter<'_>]) -> bool;
+    fn apply_filters(&self, filters: &[Box<dyn PostFilter + '_>]) -> bool;
 }
 
 impl ApplyFilters for PostMetadata {
-    fn apply_filters(&self, filters: &[Filter<'_>]) -> bool {
+    fn apply_filters(&self, filters: &[Box<dyn PostFilter + '_>]) -> bool {
         for filter in filters {
-            if !filter.apply(self) {
+            if !filter.matches(self) {
                 return false;
             }
         }
@@ -96,7 +298,7 @@ impl ApplyFilters for PostMetadata {
 pub trait PostManager {
     async fn get_all_post_metadata(
         &self,
-        filters: &[Filter<'_>],
+        filters: &[Box<dyn PostFilter + '_>],
         query: &IndexMap<String, Value>,
     ) -> Result<Vec<PostMetadata>, PostError> {
         self.get_all_posts(filters, query)
@@ -106,24 +308,39 @@ pub trait PostManager {
 
     async fn get_all_posts(
         &self,
-        filters: &[Filter<'_>],
+        filters: &[Box<dyn PostFilter + '_>],
         query: &IndexMap<String, Value>,
     ) -> Result<Vec<(PostMetadata, Arc<str>, RenderStats)>, PostError>;
 
     async fn get_max_n_post_metadata_with_optional_tag_sorted(
         &self,
         n: Option<usize>,
-        tag: Option<&str>,
+        filters: &[Box<dyn PostFilter + '_>],
+        sort: Sort,
+        order: Option<Order>,
         query: &IndexMap<String, Value>,
     ) -> Result<Vec<PostMetadata>, PostError> {
-        let filters = tag.and(Some(Filter::Tags(tag.as_slice())));
-        let mut posts = self
-            .get_all_post_metadata(filters.as_slice(), query)
-            .await?;
-        // we still want some semblance of order if created_at is None so sort by mtime as well
-        posts.sort_unstable_by_key(|metadata| metadata.modified_at.unwrap_or_default());
-        posts.sort_by_key(|metadata| metadata.written_at.unwrap_or_default());
-        posts.reverse();
+        let mut posts = self.get_all_post_metadata(filters, query).await?;
+        match sort {
+            Sort::Date => {
+                // we still want some semblance of order if created_at is None so sort by mtime as well
+                posts.sort_unstable_by_key(|metadata| metadata.modified_at.unwrap_or_default());
+                posts.sort_by_key(|metadata| metadata.written_at.unwrap_or_default());
+            }
+            Sort::Name => posts.sort_by_cached_key(|metadata| {
+                (metadata.title.to_lowercase(), Arc::clone(&metadata.name))
+            }),
+        }
+        // newest first, but alphabetical
+        if order.unwrap_or(match sort {
+            Sort::Date => Order::Desc,
+            Sort::Name => Order::Asc,
+        }) == Order::Desc
+        {
+            posts.reverse();
+        }
+        // stable, so pinned posts stay in date order among themselves
+        posts.sort_by_key(|metadata| !metadata.pinned);
         if let Some(n) = n {
             posts.truncate(n);
         }
@@ -136,18 +353,161 @@ pub trait PostManager {
         &self,
         name: Arc<str>,
         query: &IndexMap<String, Value>,
+        headers: &HeaderMap,
     ) -> Result<PostMetadata, PostError> {
-        match self.get_post(name.clone(), query).await? {
+        match self.get_post(name.clone(), query, headers).await? {
             ReturnedPost::Rendered { meta, .. } => Ok(meta),
             ReturnedPost::Raw { .. } => Err(PostError::NotFound(name)),
         }
     }
 
+    /// `headers` are the headers of the request the post is viewed with
     async fn get_post(
         &self,
         name: Arc<str>,
         query: &IndexMap<String, Value>,
+        headers: &HeaderMap,
     ) -> Result<ReturnedPost, PostError>;
 
     async fn cleanup(&self) {}
+
+    /// empties the cache, returning how many entries were removed
+    async fn clear_cache(&self) -> usize {
+        0
+    }
+
+    /// `None` if caching is disabled
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+
+    /// if a post used to be called `name`, what it's linked as now
+    async fn resolve_alias(&self, _name: &str) -> Option<Arc<str>> {
+        None
+    }
+
+    /// whether the directory posts are read from is readable
+    async fn root_ok(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// a published post with just a name and tags
+    pub(crate) fn metadata(name: &str, tags: &[&str]) -> PostMetadata {
+        PostMetadata {
+            name: name.into(),
+            slug: None,
+            aliases: Vec::new(),
+            title: name.into(),
+            description: "".into(),
+            author: "bingus".into(),
+            icon: None,
+            icon_alt: None,
+            color: None,
+            written_at: Some(DateTime::UNIX_EPOCH),
+            modified_at: None,
+            tags: tags.iter().map(|&tag| tag.into()).collect(),
+            draft: false,
+            pinned: false,
+            series: None,
+            series_index: None,
+            publish_at: None,
+            unpublish_at: None,
+            template: None,
+            raw_access: None,
+            excerpt_html: None,
+            state: PublishState::Published,
+            reading_time_minutes: None,
+            word_count: None,
+            body_bytes: 0,
+            content_hash: "".into(),
+        }
+    }
+
+    fn matching<'a>(posts: &'a [PostMetadata], listing: &ListingQuery) -> Vec<&'a str> {
+        let filters = listing_filters(listing);
+        posts
+            .iter()
+            .filter(|meta| meta.apply_filters(&filters))
+            .map(|meta| &*meta.name)
+            .collect()
+    }
+
+    #[test]
+    fn include_and_exclude_tags() {
+        let posts = [
+            metadata("async", &["rust", "async"]),
+            metadata("notes", &["rust", "draft-notes"]),
+            metadata("python", &["python"]),
+            metadata("both", &["rust", "python"]),
+        ];
+
+        let rust_not_notes = ListingQuery {
+            tags: vec!["rust"],
+            exclude: vec!["draft-notes"],
+            ..Default::default()
+        };
+        assert_eq!(matching(&posts, &rust_not_notes), ["async", "both"]);
+
+        let any = ListingQuery {
+            tags: vec!["async", "python"],
+            exclude: vec!["rust"],
+            ..Default::default()
+        };
+        assert_eq!(matching(&posts, &any), ["python"]);
+
+        let all = ListingQuery {
+            tags: vec!["rust", "python"],
+            tag_match: TagMatch::All,
+            exclude: vec!["async"],
+            ..Default::default()
+        };
+        assert_eq!(matching(&posts, &all), ["both"]);
+
+        let only_exclude = ListingQuery {
+            exclude: vec!["rust", "nonexistent"],
+            ..Default::default()
+        };
+        assert_eq!(matching(&posts, &only_exclude), ["python"]);
+    }
+
+    #[test]
+    fn scheduled_posts() {
+        let at = |date: &str| date.parse::<DateTime<Utc>>().unwrap();
+        let before = at("2024-01-01T00:00:00Z");
+        let after = at("2024-06-01T00:00:00Z");
+
+        let mut future = metadata("future", &[]);
+        future.written_at = Some(at("2024-03-01T00:00:00Z"));
+        let mut scheduled = metadata("scheduled", &[]);
+        scheduled.publish_at = Some(at("2024-03-01T00:00:00Z"));
+        let mut expiring = metadata("expiring", &[]);
+        expiring.unpublish_at = Some(at("2024-03-01T00:00:00Z"));
+        let mut draft = metadata("draft", &[]);
+        draft.draft = true;
+
+        assert_eq!(future.state_at(before), PublishState::Scheduled);
+        assert_eq!(future.state_at(after), PublishState::Published);
+        assert_eq!(scheduled.state_at(before), PublishState::Scheduled);
+        assert_eq!(scheduled.state_at(after), PublishState::Published);
+        assert_eq!(expiring.state_at(before), PublishState::Published);
+        assert_eq!(expiring.state_at(after), PublishState::Unpublished);
+        assert_eq!(draft.state_at(after), PublishState::Draft);
+
+        let posts = [future, scheduled, expiring, draft];
+        let published = |now| -> Vec<&str> {
+            let filters: [Box<dyn PostFilter>; 1] = [Box::new(Filter::Published(now))];
+            posts
+                .iter()
+                .filter(|meta| meta.apply_filters(&filters))
+                .map(|meta| &*meta.name)
+                .collect()
+        };
+        assert_eq!(published(before), ["expiring"]);
+        assert_eq!(published(after), ["future", "scheduled"]);
+    }
 }
@@ -0,0 +1,213 @@
+use std::collections::{hash_map, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use arc_swap::access::DynAccess;
+use tracing::warn;
+
+use crate::config::SlugConflict;
+use crate::error::PostError;
+use crate::post::PostMetadata;
+
+pub type SlugConflictAccess = Box<dyn DynAccess<SlugConflict> + Send + Sync>;
+
+/// the names a post can be reached by, other than its own
+pub struct PostLinks {
+    pub name: Arc<str>,
+    pub slug: Option<Arc<str>>,
+    pub aliases: Vec<Arc<str>>,
+}
+
+impl From<&PostMetadata> for PostLinks {
+    fn from(meta: &PostMetadata) -> Self {
+        Self {
+            name: Arc::clone(&meta.name),
+            slug: meta.slug.clone(),
+            aliases: meta.aliases.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Links {
+    /// slug -> name
+    slugs: HashMap<Arc<str>, Arc<str>>,
+    /// alias -> what the post is linked as
+    aliases: HashMap<Arc<str>, Arc<str>>,
+}
+
+/// how often a name that isn't in the index may cause a rebuild, so
+/// requests for names that don't exist can't go through every post each time
+const REBUILD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// maps the `slug`s posts set in their metadata to the names of the posts,
+/// so they can be served under both, and their `aliases` to where they
+/// should redirect to
+#[derive(Default)]
+pub struct SlugIndex {
+    built: AtomicBool,
+    /// when the index was last rebuilt, or a rebuild was asked for
+    checked_at: Mutex<Option<Instant>>,
+    links: RwLock<Links>,
+}
+
+impl SlugIndex {
+    /// whether a name missing from the index is worth a rebuild: true if it
+    /// was never built or not in the last [`REBUILD_INTERVAL`]. returns true
+    /// once per interval, the caller is expected to rebuild
+    pub fn needs_build(&self) -> bool {
+        let mut checked_at = self.checked_at.lock().unwrap();
+        if checked_at.is_some_and(|at| at.elapsed() < REBUILD_INTERVAL) {
+            return false;
+        }
+        *checked_at = Some(Instant::now());
+        true
+    }
+
+    /// replaces the index. a slug used by more than one post goes to the
+    /// one whose name sorts first or last, as `policy` says, the other posts
+    /// are only served by their name. with [`SlugConflict::Error`], the index
+    /// is left as it was and the conflict is returned. aliases used by more
+    /// than one post go to the one whose name sorts first
+    pub fn rebuild(
+        &self,
+        mut posts: Vec<PostLinks>,
+        policy: SlugConflict,
+    ) -> Result<(), PostError> {
+        posts.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        let mut slugs = HashMap::new();
+        for post in &posts {
+            let Some(slug) = &post.slug else {
+                continue;
+            };
+            match slugs.entry(Arc::clone(slug)) {
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(Arc::clone(&post.name));
+                }
+                hash_map::Entry::Occupied(mut entry) => match policy {
+                    SlugConflict::FirstWins => {
+                        warn!(
+                            "slug {slug:?} of {:?} is shadowed by {:?}",
+                            post.name,
+                            entry.get()
+                        );
+                    }
+                    SlugConflict::LastWins => {
+                        warn!(
+                            "slug {slug:?} of {:?} is shadowed by {:?}",
+                            entry.get(),
+                            post.name
+                        );
+                        entry.insert(Arc::clone(&post.name));
+                    }
+                    SlugConflict::Error => {
+                        return Err(PostError::SlugConflict(
+                            Arc::clone(slug),
+                            entry.get().to_string(),
+                            post.name.to_string(),
+                        ));
+                    }
+                },
+            }
+        }
+
+        let mut aliases = HashMap::new();
+        for post in &posts {
+            let target = match &post.slug {
+                Some(slug) if slugs.get(slug) == Some(&post.name) => slug,
+                _ => &post.name,
+            };
+            for alias in &post.aliases {
+                if let Some(other) = aliases.get(alias) {
+                    warn!(
+                        "alias {alias:?} of {:?} already points to {other:?}, ignoring it",
+                        post.name
+                    );
+                } else {
+                    aliases.insert(Arc::clone(alias), Arc::clone(target));
+                }
+            }
+        }
+
+        self.built.store(true, Ordering::Relaxed);
+        *self.checked_at.lock().unwrap() = Some(Instant::now());
+        *self.links.write().unwrap() = Links { slugs, aliases };
+        Ok(())
+    }
+
+    pub fn resolve(&self, slug: &str) -> Option<Arc<str>> {
+        self.links.read().unwrap().slugs.get(slug).cloned()
+    }
+
+    /// what the post that used to be called `alias` is linked as now
+    pub fn resolve_alias(&self, alias: &str) -> Option<Arc<str>> {
+        self.links.read().unwrap().aliases.get(alias).cloned()
+    }
+
+    /// drops the slug of a post if it doesn't lead back to it, so that it's
+    /// linked by its name instead
+    pub fn verify(&self, meta: &mut PostMetadata) {
+        if !self.built.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(slug) = &meta.slug
+            && self.resolve(slug).is_none_or(|name| name != meta.name)
+        {
+            meta.slug = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(name: &str, slug: &str) -> PostLinks {
+        PostLinks {
+            name: name.into(),
+            slug: Some(slug.into()),
+            aliases: Vec::new(),
+        }
+    }
+
+    fn posts() -> Vec<PostLinks> {
+        vec![
+            links("b", "hello"),
+            links("a", "hello"),
+            links("c", "other"),
+        ]
+    }
+
+    #[test]
+    fn conflict_policy() {
+        let index = SlugIndex::default();
+
+        index.rebuild(posts(), SlugConflict::FirstWins).unwrap();
+        assert_eq!(index.resolve("hello").as_deref(), Some("a"));
+        assert_eq!(index.resolve("other").as_deref(), Some("c"));
+
+        index.rebuild(posts(), SlugConflict::LastWins).unwrap();
+        assert_eq!(index.resolve("hello").as_deref(), Some("b"));
+
+        let err = index.rebuild(posts(), SlugConflict::Error).unwrap_err();
+        assert!(
+            matches!(&err, PostError::SlugConflict(slug, a, b) if &**slug == "hello" && a == "a" && b == "b"),
+            "{err:?}"
+        );
+        // left as it was
+        assert_eq!(index.resolve("hello").as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn rebuilds_are_throttled() {
+        let index = SlugIndex::default();
+        assert!(index.needs_build());
+        assert!(!index.needs_build());
+
+        let index = SlugIndex::default();
+        index.rebuild(posts(), SlugConflict::FirstWins).unwrap();
+        assert!(!index.needs_build());
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::{self, Context};
+use serde::Serialize;
+use tracing::{debug, error, info};
+use url::Url;
+
+use crate::config::PurgeConfig;
+
+/// pages that list posts and go stale whenever any post changes
+const LISTINGS: &[&str] = &["/", "/posts", "/posts.json", "/feed.xml"];
+
+#[derive(Serialize, Debug)]
+struct PurgeRequest {
+    files: Vec<Url>,
+}
+
+fn urls<'a>(
+    link: &Url,
+    base_path: &str,
+    slugs: impl Iterator<Item = &'a Arc<str>>,
+) -> eyre::Result<Vec<Url>> {
+    let mut urls = Vec::new();
+
+    for slug in slugs {
+        urls.push(link.join(&format!("{base_path}/posts/{slug}"))?);
+    }
+    for listing in LISTINGS {
+        urls.push(link.join(&format!("{base_path}{listing}"))?);
+    }
+
+    Ok(urls)
+}
+
+async fn send(client: &reqwest::Client, config: &PurgeConfig, urls: Vec<Url>) -> eyre::Result<()> {
+    let method = reqwest::Method::from_bytes(config.method.as_bytes())
+        .with_context(|| format!("invalid method {:?}", config.method))?;
+
+    let mut request = client
+        .request(method, config.endpoint.clone())
+        .json(&PurgeRequest { files: urls });
+
+    if let Some(auth) = &config.auth {
+        request = request.header("Authorization", &**auth);
+    }
+
+    request
+        .send()
+        .await
+        .context("failed to send request")?
+        .error_for_status()
+        .context("purge endpoint returned an error")?;
+
+    Ok(())
+}
+
+/// asks the configured CDN endpoint to purge the pages of the changed posts,
+/// along with every listing they show up in
+pub async fn purge<'a>(
+    client: &reqwest::Client,
+    config: &PurgeConfig,
+    link: &Url,
+    base_path: &str,
+    slugs: impl Iterator<Item = &'a Arc<str>>,
+) {
+    let urls = match urls(link, base_path, slugs) {
+        Ok(urls) => urls,
+        Err(err) => {
+            error!("failed to build urls to purge: {err}");
+            return;
+        }
+    };
+    let count = urls.len();
+    debug!("purging {urls:?}");
+
+    match send(client, config, urls).await {
+        Ok(()) => info!("purged {count} url(s) from cdn"),
+        Err(err) => error!("failed to purge cdn: {err:#}"),
+    }
+}
@@ -0,0 +1,177 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use scc::HashMap;
+
+use crate::config::RateLimitConfig;
+use crate::error::AppError;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// a token bucket per client ip
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    buckets: HashMap<IpAddr, Bucket>,
+    last_cleanup: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            per_second: config.per_second.get().into(),
+            burst: config.burst.get().into(),
+            buckets: HashMap::new(),
+            last_cleanup: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// takes a token for `ip`, or returns how long until there's one
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        self.cleanup(now);
+
+        let mut entry = self.buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let bucket = entry.get_mut();
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * self.per_second;
+        bucket.tokens = (bucket.tokens + refilled).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+
+    fn cleanup(&self, now: Instant) {
+        let Ok(mut last_cleanup) = self.last_cleanup.try_lock() else {
+            return;
+        };
+        if now.duration_since(*last_cleanup) < CLEANUP_INTERVAL {
+            return;
+        }
+        *last_cleanup = now;
+
+        // a bucket that has refilled is no different from a new one
+        let refill = Duration::from_secs_f64(self.burst / self.per_second);
+        self.buckets
+            .retain(|_, bucket| now.duration_since(bucket.updated) < refill);
+    }
+}
+
+pub async fn limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // requests without an ip (export, unix sockets) aren't limited
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical());
+
+    if let Some(Err(wait)) = ip.map(|ip| limiter.check(ip)) {
+        return (
+            [(RETRY_AFTER, retry_after(wait))],
+            AppError::TooManyRequests,
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// whole seconds to wait, rounded up and at least one
+fn retry_after(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    fn limiter(per_second: u32, burst: u32) -> RateLimiter {
+        RateLimiter::new(&RateLimitConfig {
+            enable: true,
+            per_second: NonZeroU32::new(per_second).unwrap(),
+            burst: NonZeroU32::new(burst).unwrap(),
+        })
+    }
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    #[test]
+    fn burst_is_exhausted() {
+        let limiter = limiter(1, 3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check_at(IP, now), Ok(()));
+        }
+        let wait = limiter.check_at(IP, now).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(1));
+
+        // other ips have their own bucket
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        assert_eq!(limiter.check_at(other, now), Ok(()));
+    }
+
+    #[test]
+    fn tokens_refill() {
+        let limiter = limiter(2, 2);
+        let now = Instant::now();
+
+        assert_eq!(limiter.check_at(IP, now), Ok(()));
+        assert_eq!(limiter.check_at(IP, now), Ok(()));
+        assert!(limiter.check_at(IP, now).is_err());
+
+        // half a second is one token at 2/s
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiter.check_at(IP, later), Ok(()));
+        assert!(limiter.check_at(IP, later).is_err());
+
+        // refilling stops at the burst size
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(limiter.check_at(IP, much_later), Ok(()));
+        assert_eq!(limiter.check_at(IP, much_later), Ok(()));
+        assert!(limiter.check_at(IP, much_later).is_err());
+    }
+
+    #[test]
+    fn retry_after_rounds_up() {
+        assert_eq!(retry_after(Duration::from_millis(1)), 1);
+        assert_eq!(retry_after(Duration::ZERO), 1);
+        assert_eq!(retry_after(Duration::from_secs(2)), 2);
+        assert_eq!(retry_after(Duration::from_millis(2001)), 3);
+
+        let limiter = limiter(4, 1);
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(IP, now), Ok(()));
+        let wait = limiter.check_at(IP, now).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(250));
+        assert_eq!(retry_after(wait), 1);
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt::Debug;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::connect_info::{Connected, IntoMakeServiceWithConnectInfo};
+use axum::extract::Request;
+use axum::serve::Listener;
+use axum::Router;
+use color_eyre::eyre::{self, Context};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use tokio::io::{AsyncRead, AsyncWrite};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tower::{Service, ServiceExt};
+use tracing::{debug, trace};
+
+use crate::config::{HttpConfig, TlsConfig};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn millis(millis: impl Into<u64>) -> Duration {
+    Duration::from_millis(millis.into())
+}
+
+pub fn builder(config: &HttpConfig) -> Builder<TokioExecutor> {
+    let mut builder = Builder::new(TokioExecutor::new());
+
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.keep_alive)
+        .header_read_timeout(config.header_read_timeout.map(millis));
+
+    if !config.http2 {
+        return builder.http1_only();
+    }
+
+    let mut http2 = builder.http2();
+    http2
+        .timer(TokioTimer::new())
+        .keep_alive_interval(config.keep_alive_interval.map(millis));
+    if let Some(timeout) = config.keep_alive_timeout {
+        http2.keep_alive_timeout(millis(timeout));
+    }
+    if let Some(max) = config.max_concurrent_streams {
+        http2.max_concurrent_streams(max);
+    }
+
+    builder
+}
+
+/// loads the certificate chain and key for serving HTTPS
+pub fn tls_acceptor(config: &HttpConfig, tls: &TlsConfig) -> eyre::Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(&tls.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("couldn't load certificates from {:?}", tls.cert))?;
+    let key = PrivateKeyDer::from_pem_file(&tls.key)
+        .with_context(|| format!("couldn't load private key from {:?}", tls.key))?;
+
+    let mut server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("invalid certificate or private key")?;
+    if config.http2 {
+        server_config.alpn_protocols.push(b"h2".to_vec());
+    }
+    server_config.alpn_protocols.push(b"http/1.1".to_vec());
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// connect info for unix socket connections, which carry no useful address
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct UnixPeer;
+
+#[cfg(unix)]
+impl Connected<tokio::net::unix::SocketAddr> for UnixPeer {
+    fn connect_info(_: tokio::net::unix::SocketAddr) -> Self {
+        Self
+    }
+}
+
+/// binds a unix socket at `path`, replacing a stale socket file
+#[cfg(unix)]
+pub async fn bind_unix(path: &Path) -> eyre::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            eyre::bail!("{path:?} is in use by another process");
+        }
+        debug!("removing stale socket {path:?}");
+        tokio::fs::remove_file(path)
+            .await
+            .with_context(|| format!("couldn't remove stale socket {path:?}"))?;
+    }
+    UnixListener::bind(path).with_context(|| format!("couldn't listen on {path:?}"))
+}
+
+async fn serve_connection<I, A, C>(
+    io: I,
+    remote_addr: A,
+    make_service: &mut IntoMakeServiceWithConnectInfo<Router, C>,
+    builder: &Builder<TokioExecutor>,
+    graceful: &GracefulShutdown,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    A: Debug,
+    C: Connected<A> + Clone + Send + Sync + 'static,
+{
+    // the address is moved into the connect info
+    let peer = format!("{remote_addr:?}");
+    let tower_service = make_service
+        .call(remote_addr)
+        .await
+        .unwrap_or_else(|err| match err {})
+        .map_request(|req: Request<Incoming>| req.map(Body::new));
+
+    let conn = builder
+        .serve_connection_with_upgrades(TokioIo::new(io), TowerToHyperService::new(tower_service))
+        .into_owned();
+    let conn = graceful.watch(conn);
+
+    tokio::spawn(async move {
+        if let Err(err) = conn.await {
+            trace!("failed to serve connection {peer}: {err:#}");
+        }
+    });
+}
+
+/// serves `app` on `listener`, with `C` as the connect info of connections
+pub async fn serve<L, C>(
+    mut listener: L,
+    tls: Option<TlsAcceptor>,
+    app: Router,
+    builder: Builder<TokioExecutor>,
+    token: CancellationToken,
+) where
+    L: Listener,
+    L::Addr: Debug + 'static,
+    C: Connected<L::Addr> + Clone + Send + Sync + 'static,
+{
+    let graceful = GracefulShutdown::new();
+    let mut make_service = app.into_make_service_with_connect_info::<C>();
+    // handshakes happen in their own tasks, so slow clients don't hold up the
+    // loop, and come back here to be served
+    let (handshaken_tx, mut handshaken_rx) = mpsc::unbounded_channel();
+
+    loop {
+        select! {
+            _ = token.cancelled() => {
+                debug!("not accepting new connections");
+                break;
+            },
+            (stream, remote_addr) = listener.accept() => {
+                trace!("connection {remote_addr:?} accepted");
+
+                let Some(acceptor) = &tls else {
+                    serve_connection(stream, remote_addr, &mut make_service, &builder, &graceful)
+                        .await;
+                    continue;
+                };
+                let handshake = acceptor.accept(stream);
+                let handshaken_tx = handshaken_tx.clone();
+                tokio::spawn(async move {
+                    match timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                        Ok(Ok(stream)) => {
+                            let _ = handshaken_tx.send((stream, remote_addr));
+                        }
+                        Ok(Err(err)) => trace!("TLS handshake with {remote_addr:?} failed: {err}"),
+                        Err(_) => trace!("TLS handshake with {remote_addr:?} timed out"),
+                    }
+                });
+            },
+            Some((stream, remote_addr)) = handshaken_rx.recv() => {
+                serve_connection(stream, remote_addr, &mut make_service, &builder, &graceful).await;
+            },
+        }
+    }
+
+    graceful.shutdown().await;
+}
@@ -1,8 +1,9 @@
 use std::convert::Infallible;
+use std::ops::RangeInclusive;
 use std::str::pattern::Pattern;
 
 use axum::extract::Request;
-use axum::http::{header, StatusCode};
+use axum::http::{header, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use include_dir::{Dir, DirEntry};
 use tracing::{debug, trace};
@@ -22,17 +23,79 @@ fn remove_prefixes(mut src: &str, pat: (impl Pattern + Copy)) -> &str {
     src
 }
 
-fn from_included_file(file: &'static include_dir::File<'static>) -> Response {
+// embedded files can only change with a new build
+const CACHE_CONTROL: &str = "public, max-age=31536000";
+
+fn is_text(mime_type: &mime_guess::Mime) -> bool {
+    mime_type.type_() == mime_guess::mime::TEXT
+        || (mime_type.type_() == mime_guess::mime::APPLICATION
+            && matches!(mime_type.subtype().as_str(), "javascript" | "json"))
+}
+
+/// Picks the requested range of a file, only single ranges are supported
+fn range_of(
+    range: Option<&HeaderValue>,
+    len: u64,
+) -> Result<Option<RangeInclusive<u64>>, Response> {
+    // ignoring the header is always allowed
+    let Some(range) = range.and_then(|range| range.to_str().ok()) else {
+        return Ok(None);
+    };
+    if len == 0 {
+        return Ok(None);
+    }
+
+    match http_range_header::parse_range_header(range)
+        .and_then(|parsed| parsed.validate(len))
+        .as_deref()
+    {
+        Ok([range]) => Ok(Some(range.clone())),
+        _ => Err((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{len}"))],
+        )
+            .into_response()),
+    }
+}
+
+fn from_included_file(
+    file: &'static include_dir::File<'static>,
+    range: Option<&HeaderValue>,
+) -> Response {
     let mime_type = mime_guess::from_path(file.path()).first_or_octet_stream();
+    let content_type = if is_text(&mime_type) {
+        format!("{}; charset=utf-8", mime_type.essence_str())
+    } else {
+        mime_type.essence_str().to_string()
+    };
 
-    (
-        [(
+    let headers = [
+        (
             header::CONTENT_TYPE,
-            header::HeaderValue::try_from(mime_type.essence_str()).expect("invalid mime type"),
-        )],
-        file.contents(),
-    )
-        .into_response()
+            HeaderValue::try_from(content_type).expect("invalid mime type"),
+        ),
+        (
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(CACHE_CONTROL),
+        ),
+        (header::ACCEPT_RANGES, HeaderValue::from_static("bytes")),
+    ];
+
+    let contents = file.contents();
+    match range_of(range, contents.len() as u64) {
+        Ok(Some(range)) => (
+            StatusCode::PARTIAL_CONTENT,
+            headers,
+            [(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start(), range.end(), contents.len()),
+            )],
+            &contents[*range.start() as usize..=*range.end() as usize],
+        )
+            .into_response(),
+        Ok(None) => (headers, contents).into_response(),
+        Err(response) => response,
+    }
 }
 
 pub async fn handle(
@@ -40,6 +103,7 @@ pub async fn handle(
     included_dir: &'static Dir<'static>,
 ) -> Result<Response, Infallible> {
     let path = req.uri().path();
+    let range = req.headers().get(header::RANGE);
 
     let has_dotdot = path.split('/').any(|seg| seg == "..");
     if has_dotdot {
@@ -53,7 +117,7 @@ pub async fn handle(
             trace!("{relative_path:?} is a directory, trying \"index.html\"");
             if let Some(file) = dir.get_file("index.html") {
                 debug!("{path:?} (index.html) serving from included dir");
-                return Ok(from_included_file(file));
+                return Ok(from_included_file(file, range));
             } else {
                 trace!("\"index.html\" not found in {relative_path:?} in included files");
             }
@@ -62,14 +126,14 @@ pub async fn handle(
             trace!("requested root, trying \"index.html\"");
             if let Some(file) = included_dir.get_file("index.html") {
                 debug!("{path:?} (index.html) serving from included dir");
-                return Ok(from_included_file(file));
+                return Ok(from_included_file(file, range));
             } else {
                 trace!("\"index.html\" not found in included files");
             }
         }
         Some(DirEntry::File(file)) => {
             debug!("{path:?} serving from included dir");
-            return Ok(from_included_file(file));
+            return Ok(from_included_file(file, range));
         }
         None => trace!("{relative_path:?} not found in included files"),
     };
@@ -8,6 +8,8 @@ use thiserror::Error;
 use tracing::{debug, error, info_span, trace};
 
 const TEMPLATES: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/templates");
+/// subdirectory of the custom templates directory for partials
+const PARTIALS_DIR: &str = "partials";
 
 #[derive(Error, Debug)]
 #[allow(clippy::enum_variant_names)]
@@ -28,7 +30,7 @@ fn is_ext(path: impl AsRef<Path>, ext: &str) -> bool {
     }
 }
 
-fn get_template_name(path: &Path) -> Option<&str> {
+pub(crate) fn get_template_name(path: &Path) -> Option<&str> {
     if !is_ext(path, "hbs") {
         return None;
     }
@@ -63,7 +65,7 @@ fn compile_included_file(file: &include_dir::File<'_>) -> Result<Template, Templ
     Ok(template)
 }
 
-fn compile_path(path: impl AsRef<std::path::Path>) -> Result<Template, TemplateError> {
+pub(crate) fn compile_path(path: impl AsRef<std::path::Path>) -> Result<Template, TemplateError> {
     use std::fs::OpenOptions;
     use std::io::Read;
 
@@ -115,15 +117,27 @@ pub fn new_registry<'a>(custom_templates_path: impl AsRef<Path>) -> io::Result<H
         };
     }
 
-    let read_dir = match std::fs::read_dir(custom_templates_path) {
+    let custom_templates_path = custom_templates_path.as_ref();
+    if register_dir(custom_templates_path, &mut reg)? {
+        // partials are registered last, so they override top-level templates too
+        register_dir(&custom_templates_path.join(PARTIALS_DIR), &mut reg)?;
+    }
+
+    Ok(reg)
+}
+
+/// registers every template in `dir` by its file stem, returns `false` if the
+/// directory doesn't exist
+fn register_dir(dir: &Path, reg: &mut Handlebars) -> io::Result<bool> {
+    let read_dir = match std::fs::read_dir(dir) {
         Ok(v) => v,
         Err(err) => match err.kind() {
-            io::ErrorKind::NotFound => return Ok(reg),
-            _ => panic!("{:?}", err),
+            io::ErrorKind::NotFound => return Ok(false),
+            _ => return Err(err),
         },
     };
     for entry in read_dir {
-        let entry = entry.unwrap();
+        let entry = entry?;
 
         let file_type = entry.file_type()?;
         if !file_type.is_file() {
@@ -143,11 +157,11 @@ pub fn new_registry<'a>(custom_templates_path: impl AsRef<Path>) -> io::Result<H
             }
         };
 
-        match register_path(&path, name, &mut reg) {
+        match register_path(&path, name, reg) {
             Ok(()) => debug!("registered template {name:?}"),
             Err(err) => error!("error while registering template: {err}"),
         };
     }
 
-    Ok(reg)
+    Ok(true)
 }
@@ -12,15 +12,44 @@ use tracing::{debug, debug_span, error, info, instrument, trace};
 
 use crate::templates::*;
 
+/// compiles whichever version of the template is active: a custom partial,
+/// then a custom template, then the embedded one. `None` if there are none
+async fn resolve_template(root: &Path, name: &str) -> Result<Option<Template>, TemplateError> {
+    let file_name = format!("{name}.hbs");
+    for path in [
+        root.join(PARTIALS_DIR).join(&file_name),
+        root.join(&file_name),
+    ] {
+        match compile_path_async_io(&path).await {
+            Ok(compiled) => {
+                debug!("compiled template {name:?} from {path:?}");
+                return Ok(Some(compiled));
+            }
+            Err(TemplateError::IoError(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    match TEMPLATES.get_file(&file_name) {
+        Some(file) => {
+            debug!("compiled embedded template {name:?}");
+            compile_included_file(file).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
 async fn process_event(
     event: DebouncedEvent,
-    templates: &mut Vec<(String, Template)>,
+    root: &Path,
+    templates: &mut Vec<(String, Option<Template>)>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     match event.kind {
         notify::EventKind::Create(notify::event::CreateKind::File)
-        | notify::EventKind::Modify(_) => {
+        | notify::EventKind::Modify(_)
+        | notify::EventKind::Remove(notify::event::RemoveKind::File) => {
             for path in &event.paths {
-                let span = debug_span!("modify_event", ?path);
+                let span = debug_span!("template_event", ?path);
                 let _handle = span.enter();
 
                 let template_name = match get_template_name(path) {
@@ -31,46 +60,35 @@ async fn process_event(
                     }
                 };
 
-                trace!("processing recompilation");
-                let compiled = compile_path_async_io(path).await?;
-                debug!("compiled template {template_name:?}");
+                // the file that changed isn't necessarily the one in use, so
+                // go by what's on disk now
+                trace!("processing change");
+                let compiled = resolve_template(root, template_name).await?;
                 templates.push((template_name.to_owned(), compiled));
             }
         }
-        notify::EventKind::Remove(notify::event::RemoveKind::File) => {
-            for path in &event.paths {
-                let span = debug_span!("remove_event", ?path);
-                let _handle = span.enter();
-
-                let (file_name, template_name) = match path
-                    .file_name()
-                    .and_then(|o| o.to_str())
-                    .and_then(|file_name| {
-                        get_template_name(Path::new(file_name))
-                            .map(|template_name| (file_name, template_name))
-                    }) {
-                    Some(v) => v,
-                    None => {
-                        trace!("skipping event");
-                        continue;
-                    }
-                };
-
-                trace!("processing removal");
-                let file = TEMPLATES.get_file(file_name);
-                if let Some(file) = file {
-                    let compiled = compile_included_file(file)?;
-                    debug!("compiled template {template_name:?}");
-                    templates.push((template_name.to_owned(), compiled));
-                }
-            }
-        }
         _ => {}
     };
 
     Ok(())
 }
 
+/// registers the compiled templates, or unregisters them if they're gone
+fn update_registry(reg: &mut Handlebars<'_>, templates: Vec<(String, Option<Template>)>) {
+    for (name, template) in templates {
+        match template {
+            Some(template) => {
+                debug!("registered template {name}");
+                reg.register_template(&name, template);
+            }
+            None => {
+                debug!("unregistered template {name}");
+                reg.unregister_template(&name);
+            }
+        }
+    }
+}
+
 #[instrument(skip_all)]
 pub async fn watch_templates<'a>(
     path: impl AsRef<Path>,
@@ -87,6 +105,10 @@ pub async fn watch_templates<'a>(
     })?;
 
     debouncer.watch(path, notify::RecursiveMode::NonRecursive)?;
+    let partials = path.join(PARTIALS_DIR);
+    if partials.is_dir() {
+        debouncer.watch(&partials, notify::RecursiveMode::NonRecursive)?;
+    }
 
     'event_loop: while let Some(events) = select! {
         _ = watcher_token.cancelled() => {
@@ -106,18 +128,13 @@ pub async fn watch_templates<'a>(
         let mut templates = Vec::new();
 
         for event in events {
-            if let Err(err) = process_event(event, &mut templates).await {
+            if let Err(err) = process_event(event, path, &mut templates).await {
                 error!("error while processing event: {err}");
             }
         }
 
         if !templates.is_empty() {
-            let mut reg = reg.write().await;
-            for template in templates.into_iter() {
-                debug!("registered template {}", template.0);
-                reg.register_template(&template.0, template.1);
-            }
-            drop(reg);
+            update_registry(&mut *reg.write().await, templates);
 
             info!("updated custom templates");
         }
@@ -125,3 +142,80 @@ pub async fn watch_templates<'a>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    use notify::event::{CreateKind, RemoveKind};
+    use notify::{Event, EventKind};
+
+    use super::*;
+
+    /// a registry that's kept up to date like the watcher does it
+    struct Watched {
+        root: PathBuf,
+        reg: Handlebars<'static>,
+    }
+
+    impl Watched {
+        async fn event(&mut self, kind: EventKind, path: PathBuf) {
+            let event = DebouncedEvent::new(Event::new(kind).add_path(path), Instant::now());
+            let mut templates = Vec::new();
+            process_event(event, &self.root, &mut templates)
+                .await
+                .unwrap();
+            update_registry(&mut self.reg, templates);
+        }
+
+        async fn write(&mut self, path: &str, contents: &str) {
+            let path = self.root.join(path);
+            std::fs::write(&path, contents).unwrap();
+            self.event(EventKind::Create(CreateKind::File), path).await;
+        }
+
+        async fn remove(&mut self, path: &str) {
+            let path = self.root.join(path);
+            std::fs::remove_file(&path).unwrap();
+            self.event(EventKind::Remove(RemoveKind::File), path).await;
+        }
+
+        fn render(&self, name: &str) -> Option<String> {
+            self.reg
+                .has_template(name)
+                .then(|| self.reg.render(name, &()).unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn add_override_and_remove() {
+        let root =
+            std::env::temp_dir().join(format!("bingus-blog-templates-{}", std::process::id()));
+        std::fs::create_dir_all(root.join(PARTIALS_DIR)).unwrap();
+        let mut watched = Watched {
+            reg: new_registry(&root).unwrap(),
+            root,
+        };
+        let embedded = watched.render("title").unwrap();
+
+        // a template that only exists as a custom one
+        assert_eq!(watched.render("custom"), None);
+        watched.write("custom.hbs", "custom").await;
+        assert_eq!(watched.render("custom").as_deref(), Some("custom"));
+        watched.remove("custom.hbs").await;
+        assert_eq!(watched.render("custom"), None);
+
+        // overriding an embedded one, then overriding that with a partial
+        watched.write("title.hbs", "mine").await;
+        assert_eq!(watched.render("title").as_deref(), Some("mine"));
+        watched.write("partials/title.hbs", "partial").await;
+        assert_eq!(watched.render("title").as_deref(), Some("partial"));
+        watched.remove("partials/title.hbs").await;
+        assert_eq!(watched.render("title").as_deref(), Some("mine"));
+        watched.remove("title.hbs").await;
+        assert_eq!(watched.render("title"), Some(embedded));
+
+        std::fs::remove_dir_all(&watched.root).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// lowercase ASCII letters and digits, with runs of anything else turned into
+/// single hyphens. other scripts are transliterated first, so `Café` becomes
+/// `cafe`
+pub fn slugify(text: &str) -> String {
+    let text = deunicode::deunicode(text);
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(slug.trim_end_matches('-').len());
+    slug
+}
+
+/// number of words in the text of rendered HTML
+pub fn word_count(html: &str) -> u32 {
+    strip_html(html).split_whitespace().count() as u32
+}
+
+/// estimated time to read `words` words, rounded up to the minute
+pub fn reading_time(words: u32, words_per_minute: u32) -> Option<u32> {
+    if words_per_minute == 0 {
+        return None;
+    }
+
+    Some(words.div_ceil(words_per_minute))
+}
+
+/// plain text of the first paragraph of rendered HTML
+pub fn first_paragraph(html: &str) -> Option<String> {
+    let start = html.find("<p>")? + "<p>".len();
+    let end = html[start..]
+        .find("</p>")
+        .map_or(html.len(), |end| start + end);
+
+    let text = strip_html(&html[start..end])
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    (!text.is_empty()).then_some(text)
+}
+
+/// truncates text to at most `max` characters at a word boundary,
+/// appending an ellipsis if anything was cut off
+pub fn truncate_words(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_owned();
+    }
+
+    let mut truncated = String::with_capacity(max + '…'.len_utf8());
+    let mut len = 0;
+    for word in text.split_whitespace() {
+        let word_len = word.chars().count();
+        let sep = usize::from(len != 0);
+        if len + sep + word_len >= max {
+            break;
+        }
+        if sep != 0 {
+            truncated.push(' ');
+        }
+        truncated += word;
+        len += sep + word_len;
+    }
+
+    // the first word alone is too long
+    if truncated.is_empty() {
+        truncated.extend(text.chars().take(max.saturating_sub(1)));
+    }
+
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_short_input() {
+        assert_eq!(truncate_words("hello world", 20), "hello world");
+        // exactly at the limit
+        assert_eq!(truncate_words("hello world", 11), "hello world");
+        assert_eq!(truncate_words("", 0), "");
+    }
+
+    #[test]
+    fn truncate_at_word_boundary() {
+        let truncated = truncate_words("hello world again", 11);
+        assert_eq!(truncated, "hello…");
+        assert!(truncated.chars().count() <= 11);
+    }
+
+    #[test]
+    fn truncate_multibyte() {
+        let truncated = truncate_words("héllo wörld ünïcödé", 12);
+        assert_eq!(truncated, "héllo wörld…");
+        assert_eq!(truncated.chars().count(), 12);
+
+        assert_eq!(truncate_words("🦀🦀 🦀🦀🦀", 6), "🦀🦀 🦀🦀🦀");
+        assert_eq!(truncate_words("🦀🦀 🦀🦀🦀", 5), "🦀🦀…");
+    }
+
+    #[test]
+    fn truncate_long_first_word() {
+        assert_eq!(truncate_words("supercalifragilistic word", 5), "supe…");
+        assert_eq!(truncate_words("ñññññññ", 4), "ñññ…");
+    }
+}
@@ -0,0 +1,92 @@
+//! checks for the parts of a config that parse fine but would only fail once
+//! the server is running
+
+use std::path::Path;
+
+use color_eyre::eyre::{self, bail, eyre, Context};
+
+use crate::config::{Compression, Config, EngineMode};
+use crate::markdown_render::load_themes;
+use crate::templates::{compile_path, get_template_name};
+
+/// returns a short status on success
+pub type Check = fn(&Config) -> eyre::Result<String>;
+
+fn check_dir(path: &Path) -> eyre::Result<String> {
+    let read_dir = std::fs::read_dir(path).map_err(|err| eyre!("{path:?}: {err}"))?;
+    Ok(format!("{path:?} has {} entries", read_dir.count()))
+}
+
+pub fn check_posts_root(config: &Config) -> eyre::Result<String> {
+    match config.engine.mode {
+        EngineMode::Markdown => check_dir(&config.engine.markdown.root),
+        EngineMode::Blag => check_dir(&config.engine.blag.root),
+    }
+}
+
+pub fn check_templates(config: &Config) -> eyre::Result<String> {
+    let path = &config.dirs.templates;
+    let read_dir = std::fs::read_dir(path).map_err(|err| eyre!("{path:?}: {err}"))?;
+
+    let mut compiled = 0;
+    for entry in read_dir {
+        let path = entry?.path();
+        if get_template_name(&path).is_none() {
+            continue;
+        }
+        compile_path(&path).map_err(|err| eyre!("{path:?}: {err}"))?;
+        compiled += 1;
+    }
+    Ok(format!("{path:?} has {compiled} valid templates"))
+}
+
+pub fn check_theme(config: &Config) -> eyre::Result<String> {
+    let render = &config.engine.markdown.render;
+    let themes = load_themes(render)?;
+    match render.syntect.theme.as_deref() {
+        Some(theme) if themes.themes.contains_key(theme) => Ok(format!("{theme:?} found")),
+        Some(theme) => bail!(
+            "{theme:?} not found, available: {:?}",
+            themes.themes.keys().collect::<Vec<_>>()
+        ),
+        None => Ok("no theme set".into()),
+    }
+}
+
+pub fn check_rss_link(config: &Config) -> eyre::Result<String> {
+    let link = &config.rss.link;
+    if !matches!(link.scheme(), "http" | "https") || link.host().is_none() {
+        bail!("{link} isn't an absolute http(s) url");
+    }
+    Ok(link.to_string())
+}
+
+pub fn check_compression_level(config: &Config) -> eyre::Result<String> {
+    let cache = &config.cache;
+    let level = cache.compression_level;
+    if !cache.compress || cache.compression != Compression::Zstd {
+        return Ok(format!("{level}, unused without zstd"));
+    }
+    let range = zstd::compression_level_range();
+    if !range.contains(&level) {
+        bail!("{level} is outside {range:?}");
+    }
+    Ok(level.to_string())
+}
+
+/// the checks a reloaded config has to pass before it replaces the current
+/// one. templates aren't checked, since the built-in ones are used in place
+/// of missing ones and the directory isn't reloaded anyway
+pub const RELOAD_CHECKS: [(&str, Check); 4] = [
+    ("posts root", check_posts_root),
+    ("syntect theme", check_theme),
+    ("rss link", check_rss_link),
+    ("compression level", check_compression_level),
+];
+
+pub fn validate(config: &Config) -> eyre::Result<()> {
+    for (name, check) in RELOAD_CHECKS {
+        check(config).with_context(|| format!("{name} check failed"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use color_eyre::eyre::{self, Context};
+use hmac::{Hmac, Mac};
+use indexmap::IndexMap;
+use notify_debouncer_full::notify::event::{ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{EventKind, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebouncedEvent};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, instrument, trace, warn};
+
+use crate::config::{Config, WebhookConfig};
+use crate::path::relative_name;
+use crate::purge::purge;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Created,
+    Updated,
+    Deleted,
+}
+
+#[derive(Serialize, Debug)]
+struct Change {
+    slug: Arc<str>,
+    action: Action,
+}
+
+#[derive(Serialize, Debug)]
+struct Payload {
+    changes: Vec<Change>,
+}
+
+fn slug(root: &Path, path: &Path, ext: &str) -> Option<Arc<str>> {
+    if path.extension()? != ext {
+        return None;
+    }
+
+    Some(relative_name(root, path)?.into())
+}
+
+fn collect_changes(
+    events: Vec<DebouncedEvent>,
+    root: &Path,
+    ext: &str,
+) -> IndexMap<Arc<str>, Action> {
+    let mut changes = IndexMap::new();
+
+    for event in events {
+        let actions: Vec<(&Path, Action)> = match event.kind {
+            EventKind::Create(_) => event
+                .paths
+                .iter()
+                .map(|p| (&**p, Action::Created))
+                .collect(),
+            EventKind::Remove(_) => event
+                .paths
+                .iter()
+                .map(|p| (&**p, Action::Deleted))
+                .collect(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => event
+                .paths
+                .iter()
+                .map(|p| (&**p, Action::Deleted))
+                .collect(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => event
+                .paths
+                .iter()
+                .map(|p| (&**p, Action::Created))
+                .collect(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => match &event.paths[..] {
+                [from, to] => vec![(&**from, Action::Deleted), (&**to, Action::Created)],
+                _ => continue,
+            },
+            EventKind::Modify(_) => event
+                .paths
+                .iter()
+                .map(|p| (&**p, Action::Updated))
+                .collect(),
+            _ => {
+                trace!("not interested: {event:?}");
+                continue;
+            }
+        };
+
+        for (path, action) in actions {
+            let Some(slug) = slug(root, path, ext) else {
+                continue;
+            };
+
+            // keep "created" for posts that were created and then written to
+            match (changes.get(&slug), action) {
+                (Some(Action::Created), Action::Updated) => {}
+                _ => {
+                    changes.insert(slug, action);
+                }
+            }
+        }
+    }
+
+    changes
+}
+
+fn build_request(
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    body: &str,
+) -> eyre::Result<reqwest::RequestBuilder> {
+    let method = reqwest::Method::from_bytes(config.method.as_bytes())
+        .with_context(|| format!("invalid method {:?}", config.method))?;
+
+    let mut request = client
+        .request(method, config.url.clone())
+        .header("Content-Type", "application/json")
+        .body(body.to_owned());
+
+    if let Some(secret) = &config.secret {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .context("failed to create signature")?;
+        mac.update(body.as_bytes());
+        let signature = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .fold(String::new(), |mut acc, byte| {
+                let _ = write!(acc, "{byte:02x}");
+                acc
+            });
+        request = request.header("X-Bingus-Signature", format!("sha256={signature}"));
+    }
+
+    Ok(request)
+}
+
+async fn deliver(request: eyre::Result<reqwest::RequestBuilder>) -> eyre::Result<()> {
+    request?
+        .send()
+        .await
+        .context("failed to send request")?
+        .error_for_status()
+        .context("webhook returned an error")?;
+
+    Ok(())
+}
+
+async fn send(client: &reqwest::Client, config: &WebhookConfig, payload: &Payload) {
+    let body = serde_json::to_string(payload).expect("payload to serialize");
+    let mut backoff = Duration::from_secs(1);
+    let mut attempt = 0;
+
+    loop {
+        match deliver(build_request(client, config, &body)).await {
+            Ok(()) => {
+                info!("delivered webhook for {} change(s)", payload.changes.len());
+                return;
+            }
+            Err(err) if attempt < config.retries => {
+                warn!("failed to deliver webhook, retrying in {backoff:?}: {err:#}");
+            }
+            Err(err) => {
+                error!("failed to deliver webhook, giving up: {err:#}");
+                return;
+            }
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// notifies the configured webhook and purges the CDN when posts change
+#[instrument(skip_all)]
+pub async fn watcher(
+    root: impl AsRef<Path>,
+    ext: &'static str,
+    recursive: bool,
+    watcher_token: CancellationToken,
+    config: Arc<ArcSwap<Config>>,
+) -> eyre::Result<()> {
+    let root = root.as_ref();
+    let debounce = Duration::from_millis(config.load().webhook.debounce);
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+    let mut debouncer = new_debouncer(debounce, None, move |events| {
+        tx.blocking_send(events)
+            .expect("failed to send message over channel")
+    })?;
+
+    debouncer
+        .watch(
+            root,
+            if recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            },
+        )
+        .with_context(|| format!("failed to watch {root:?}"))?;
+
+    let client = reqwest::Client::new();
+
+    'event_loop: while let Some(events) = select! {
+        _ = watcher_token.cancelled() => {
+            debug!("exiting webhook watcher loop");
+            break 'event_loop;
+        },
+        events = rx.recv() => events,
+    } {
+        let events = match events {
+            Ok(events) => events,
+            Err(err) => {
+                error!("error getting events: {err:?}");
+                continue;
+            }
+        };
+
+        let changes = collect_changes(events, root, ext);
+        if changes.is_empty() {
+            continue;
+        }
+
+        let payload = Payload {
+            changes: changes
+                .into_iter()
+                .map(|(slug, action)| Change { slug, action })
+                .collect(),
+        };
+        let config = config.load_full();
+
+        let webhook = async {
+            if config.webhook.enable {
+                debug!("sending webhook: {payload:?}");
+                send(&client, &config.webhook, &payload).await;
+            }
+        };
+        let purge = async {
+            if config.purge.enable {
+                let slugs = payload.changes.iter().map(|change| &change.slug);
+                purge(
+                    &client,
+                    &config.purge,
+                    &config.rss.link,
+                    &config.http.base_path,
+                    slugs,
+                )
+                .await;
+            }
+        };
+
+        select! {
+            _ = watcher_token.cancelled() => break 'event_loop,
+            _ = async { tokio::join!(webhook, purge) } => {},
+        }
+    }
+
+    Ok(())
+}
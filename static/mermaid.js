@@ -0,0 +1,4 @@
+// only included on pages that have diagrams for mermaid.js
+import(document.currentScript.dataset.mermaid).then(({ default: mermaid }) =>
+	mermaid.run(),
+);
@@ -2,10 +2,15 @@ function populateByName(source, target) {
 	let posts = [];
 	for (let post of source.children) {
 		let title = post.firstElementChild.innerText;
-		posts.push([title, post.cloneNode(true)]);
+		let pinned = post.classList.contains("pinned");
+		posts.push([title, pinned, post.cloneNode(true)]);
 	}
-	posts.sort(([a, _1], [b, _2]) => a.toLocaleLowerCase().localeCompare(b.toLocaleLowerCase()));
-	for (let [_, post] of posts) {
+	// pinned posts stay on top
+	posts.sort(
+		([a, aPinned, _1], [b, bPinned, _2]) =>
+			bPinned - aPinned || a.toLocaleLowerCase().localeCompare(b.toLocaleLowerCase()),
+	);
+	for (let [_1, _2, post] of posts) {
 		target.appendChild(post);
 	}
 }